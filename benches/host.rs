@@ -0,0 +1,37 @@
+// Copyright 2016 Simon Sapin.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Run with `cargo bench` on a nightly compiler.
+
+#![feature(test)]
+
+extern crate test;
+extern crate url;
+
+use test::{Bencher, black_box};
+use url::Host;
+
+#[bench]
+fn parse_ascii_host(b: &mut Bencher) {
+    b.iter(|| Host::parse(black_box("www.Example.COM")).unwrap())
+}
+
+#[bench]
+fn parse_percent_encoded_ascii_host(b: &mut Bencher) {
+    b.iter(|| Host::parse(black_box("www.%45xample.com")).unwrap())
+}
+
+#[bench]
+fn parse_punycode_host(b: &mut Bencher) {
+    b.iter(|| Host::parse(black_box("www.xn--9ca.com")).unwrap())
+}
+
+#[bench]
+fn parse_unicode_host(b: &mut Bencher) {
+    b.iter(|| Host::parse(black_box("www.☃.net")).unwrap())
+}
@@ -21,22 +21,32 @@ print('''\
 
 // Generated by make_encode_sets.py
 ''')
+print(
+    "pub static PERCENT_ENCODED: [&'static str; 256] = [\n%s\n];\n\n"
+    % '\n'.join(
+        '   ' + ' '.join('"%%%02X",' % b for b in range(s, s + 8))
+        for s in range(0, 256, 8)))
+
+# Bit `b % 32` of item `b / 32` is set if byte `b` is percent-encoded.
 for name, encoded in [
     ('SIMPLE',   ''),
     ('QUERY',    r''' "#<>'''),
+    ('FRAGMENT', r''' "<>`'''),
     ('DEFAULT',  r''' "#<>`?{}'''),
     ('USERINFO', r''' "#<>`?{}@'''),
     ('PASSWORD', r''' "#<>`?{}@\/'''),
     ('USERNAME', r''' "#<>`?{}@\/:'''),
     ('FORM_URLENCODED', r''' !"#$%&\'()+,/:;<=>?@[\]^`{|}~'''),
     ('HTTP_VALUE', r''' "%'()*,/:;<->?[\]{}'''),
+    ('TEMPLATE_VALUE', r''' !"#$%&'()*+,/:;<=>?@[\]^`{|}'''),
 ]:
+    bits = [
+        sum(1 << (b % 32) for b in range(s, s + 32)
+            if not (0x20 <= b <= 0x7E and chr(b) not in encoded))
+        for s in range(0, 256, 32)
+    ]
     print(
-        "pub static %s: [&'static str; 256] = [\n%s\n];\n\n"
+        "pub const %s: [u32; 8] = [\n%s\n];\n\n"
         % (name, '\n'.join(
-            '   ' + ' '.join(
-                '"%s%s",' % ("\\" if chr(b) in '\\"' else "", chr(b))
-                if 0x20 <= b <= 0x7E and chr(b) not in encoded
-                else '"%%%02X",' % b
-                for b in range(s, s + 8)
-            ) for s in range(0, 256, 8))))
+            '    ' + ' '.join('0x%08X,' % b for b in bits[s:s + 4])
+            for s in range(0, 8, 4))))
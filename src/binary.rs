@@ -0,0 +1,257 @@
+// Copyright 2016 Simon Sapin.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A compact binary format for `Url`, used by `Url::to_bytes` and `Url::from_bytes`.
+//!
+//! The first byte is the format version.
+//! Strings are a big-endian `u32` length followed by UTF-8 bytes,
+//! optional values a `0` or `1` byte followed by the value if present.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str;
+use super::{Url, SchemeData, RelativeSchemeData, SchemeType};
+use super::{whatwg_scheme_type_mapper, is_special_scheme};
+use host::{self, Host};
+use parser;
+use percent_encoding::{
+    EncodeSet, SIMPLE_ENCODE_SET, DEFAULT_ENCODE_SET, USERINFO_ENCODE_SET, QUERY_ENCODE_SET
+};
+
+const VERSION: u8 = 1;
+
+
+pub fn write_url(url: &Url, output: &mut Vec<u8>) {
+    output.push(VERSION);
+    write_str(&url.scheme, output);
+    match url.scheme_data {
+        SchemeData::Relative(ref data) => {
+            output.push(0);
+            write_str(&data.username, output);
+            write_option(data.password.as_ref(), output, |password, output| {
+                write_str(password, output)
+            });
+            match data.host {
+                Host::Domain(ref domain) => {
+                    output.push(0);
+                    write_str(domain, output)
+                }
+                Host::Ipv4(address) => {
+                    output.push(1);
+                    output.extend(address.octets().iter().cloned())
+                }
+                Host::Ipv6(address) => {
+                    output.push(2);
+                    for &segment in &address.segments() {
+                        write_u16(segment, output)
+                    }
+                }
+                Host::IpvFuture(ref address) => {
+                    output.push(3);
+                    write_str(address, output)
+                }
+            }
+            write_option(data.port, output, write_u16);
+            write_option(data.default_port, output, write_u16);
+            write_u32(data.path.len() as u32, output);
+            for component in &data.path {
+                write_str(component, output)
+            }
+        }
+        SchemeData::NonRelative(ref scheme_data) => {
+            output.push(1);
+            write_str(scheme_data, output)
+        }
+    }
+    write_option(url.query.as_ref(), output, |query, output| write_str(query, output));
+    write_option(url.fragment.as_ref(), output, |fragment, output| write_str(fragment, output));
+}
+
+fn write_u16(value: u16, output: &mut Vec<u8>) {
+    output.push((value >> 8) as u8);
+    output.push(value as u8);
+}
+
+fn write_u32(value: u32, output: &mut Vec<u8>) {
+    write_u16((value >> 16) as u16, output);
+    write_u16(value as u16, output);
+}
+
+fn write_str(value: &str, output: &mut Vec<u8>) {
+    write_u32(value.len() as u32, output);
+    output.extend(value.as_bytes().iter().cloned())
+}
+
+fn write_option<T, F>(value: Option<T>, output: &mut Vec<u8>, write: F)
+where F: FnOnce(T, &mut Vec<u8>) {
+    match value {
+        Some(value) => {
+            output.push(1);
+            write(value, output)
+        }
+        None => output.push(0),
+    }
+}
+
+
+pub fn read_url(input: &[u8]) -> Result<Url, ()> {
+    let mut reader = Reader { input: input };
+    if try!(reader.u8()) != VERSION {
+        return Err(())
+    }
+    let scheme = try!(reader.string());
+    let scheme_data = match try!(reader.u8()) {
+        0 => {
+            let username = try!(reader.string());
+            let password = try!(reader.option(Reader::string));
+            let host = match try!(reader.u8()) {
+                0 => Host::Domain(try!(reader.string())),
+                1 => {
+                    let octets = try!(reader.bytes(4));
+                    Host::Ipv4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+                }
+                2 => {
+                    let mut segments = [0; 8];
+                    for segment in &mut segments {
+                        *segment = try!(reader.u16());
+                    }
+                    Host::Ipv6(Ipv6Addr::new(segments[0], segments[1], segments[2], segments[3],
+                                             segments[4], segments[5], segments[6], segments[7]))
+                }
+                3 => Host::IpvFuture(try!(reader.string())),
+                _ => return Err(())
+            };
+            let port = try!(reader.option(Reader::u16));
+            let default_port = try!(reader.option(Reader::u16));
+            let path_len = try!(reader.u32());
+            let mut path = Vec::new();
+            for _ in 0..path_len {
+                path.push(try!(reader.string()));
+            }
+            SchemeData::Relative(RelativeSchemeData {
+                username: username,
+                password: password,
+                host: host,
+                port: port,
+                default_port: default_port,
+                path: path,
+            })
+        }
+        1 => SchemeData::NonRelative(try!(reader.string())),
+        _ => return Err(())
+    };
+    let query = try!(reader.option(Reader::string));
+    let fragment = try!(reader.option(Reader::string));
+    if !reader.input.is_empty() {
+        return Err(())
+    }
+    let url = Url {
+        scheme: scheme,
+        scheme_data: scheme_data,
+        query: query,
+        fragment: fragment,
+    };
+    if is_valid(&url) { Ok(url) } else { Err(()) }
+}
+
+/// Whether `url` has components the parser could have produced, checked without parsing:
+/// the scheme’s characters and type, the host’s syntax, a non-default port,
+/// and that each other component is percent-encoded and free of the delimiters that end it.
+///
+/// Custom scheme types are allowed, but special schemes must have their standard one.
+fn is_valid(url: &Url) -> bool {
+    if !is_valid_scheme(&url.scheme) {
+        return false
+    }
+    let scheme_type = match url.scheme_data {
+        SchemeData::Relative(ref data) => match data.default_port {
+            Some(port) => SchemeType::Relative(port),
+            None => SchemeType::FileLike,
+        },
+        SchemeData::NonRelative(..) => SchemeType::NonRelative,
+    };
+    if is_special_scheme(&url.scheme) && scheme_type != whatwg_scheme_type_mapper(&url.scheme) {
+        return false
+    }
+    let scheme_data_is_valid = match url.scheme_data {
+        SchemeData::Relative(ref data) => {
+            is_encoded(&data.username, USERINFO_ENCODE_SET, ":/\\")
+            && data.password.as_ref().map_or(true, |password| {
+                is_encoded(password, USERINFO_ENCODE_SET, "/\\")
+            })
+            && host::is_parsed_host(&data.host)
+            && !(is_special_scheme(&url.scheme) && matches!(data.host, Host::IpvFuture(..)))
+            && (scheme_type == SchemeType::FileLike || data.host != Host::Domain(String::new()))
+            && (data.port.is_none() || data.port != data.default_port)
+            && data.path.iter().all(|component| {
+                is_encoded(component, DEFAULT_ENCODE_SET, "/\\")
+                && !parser::is_single_dot_path_segment(component)
+                && !parser::is_double_dot_path_segment(component)
+            })
+        }
+        SchemeData::NonRelative(ref scheme_data) => {
+            is_encoded(scheme_data, SIMPLE_ENCODE_SET, "?#")
+        }
+    };
+    scheme_data_is_valid
+    && url.query.as_ref().map_or(true, |query| is_encoded(query, QUERY_ENCODE_SET, "#"))
+    && url.fragment.as_ref().map_or(true, |fragment| is_encoded(fragment, SIMPLE_ENCODE_SET, ""))
+}
+
+fn is_valid_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c| matches!(c, 'a'...'z'))
+    && scheme.bytes().all(|b| matches!(b, b'a'...b'z' | b'0'...b'9' | b'+' | b'-' | b'.'))
+}
+
+/// Whether `input` has no byte that `encode_set` or `delimiters` would percent-encode.
+fn is_encoded(input: &str, encode_set: EncodeSet, delimiters: &str) -> bool {
+    input.bytes().all(|b| !encode_set.contains(b) && !delimiters.as_bytes().contains(&b))
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ()> {
+        if self.input.len() < len {
+            return Err(())
+        }
+        let (bytes, remaining) = self.input.split_at(len);
+        self.input = remaining;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ()> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ()> {
+        self.bytes(2).map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, ()> {
+        let high = try!(self.u16());
+        let low = try!(self.u16());
+        Ok((high as u32) << 16 | low as u32)
+    }
+
+    fn string(&mut self) -> Result<String, ()> {
+        let len = try!(self.u32());
+        let bytes = try!(self.bytes(len as usize));
+        str::from_utf8(bytes).map(str::to_owned).map_err(|_| ())
+    }
+
+    fn option<T, F>(&mut self, read: F) -> Result<Option<T>, ()>
+    where F: FnOnce(&mut Reader<'a>) -> Result<T, ()> {
+        match try!(self.u8()) {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            _ => Err(())
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2016 Simon Sapin.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decoding of [`data:` URLs](https://tools.ietf.org/html/rfc2397).
+
+use std::ascii::AsciiExt;
+use rustc_serialize::base64::FromBase64;
+use percent_encoding::percent_decode;
+use super::{Url, SchemeData};
+
+
+/// The components of a `data:` URL. See `Url::data_url`.
+///
+/// ```rust
+/// use url::Url;
+///
+/// let url = Url::parse("data:text/plain;base64,SGVsbG8=").unwrap();
+/// let data_url = url.data_url().unwrap();
+/// assert_eq!(data_url.mime_type(), "text/plain");
+/// assert!(data_url.is_base64());
+/// assert_eq!(data_url.decode_to_vec().unwrap(), b"Hello");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataUrl {
+    mime_type: String,
+    base64: bool,
+    body: String,
+}
+
+impl DataUrl {
+    /// Split a `data:` URL into its MIME type and its still encoded body.
+    ///
+    /// Returns `None` if the URL is not in the `data` scheme or has no `,` comma.
+    pub fn from_url(url: &Url) -> Option<DataUrl> {
+        let scheme_data = match url.scheme_data {
+            SchemeData::NonRelative(ref scheme_data) if url.scheme == "data" => scheme_data,
+            _ => return None,
+        };
+        let comma = match scheme_data.find(',') {
+            Some(comma) => comma,
+            None => return None,
+        };
+        let mut mime_type = trim_ascii_whitespace(&scheme_data[..comma]);
+        // The query string is part of the body, the fragment identifier is not.
+        let mut body = scheme_data[comma + 1..].to_owned();
+        if let Some(ref query) = url.query {
+            body.push('?');
+            body.push_str(query);
+        }
+
+        let mut base64 = false;
+        if let Some(semicolon) = mime_type.rfind(';') {
+            if trim_ascii_whitespace(&mime_type[semicolon + 1..]).eq_ignore_ascii_case("base64") {
+                base64 = true;
+                mime_type = trim_ascii_whitespace(&mime_type[..semicolon]);
+            }
+        }
+        let mime_type = if mime_type.is_empty() {
+            "text/plain;charset=US-ASCII".to_owned()
+        } else if mime_type.starts_with(';') {
+            format!("text/plain{}", mime_type)
+        } else {
+            mime_type.to_owned()
+        };
+        Some(DataUrl { mime_type: mime_type, base64: base64, body: body })
+    }
+
+    /// Return the MIME type, with any parameters such as `charset`, as written in the URL.
+    ///
+    /// This defaults to `text/plain;charset=US-ASCII` if the URL has none.
+    #[inline]
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// Return whether the body is base64-encoded, with a `;base64` suffix to the MIME type.
+    #[inline]
+    pub fn is_base64(&self) -> bool {
+        self.base64
+    }
+
+    /// Percent-decode the body, then decode it as base64 if `is_base64` is true.
+    ///
+    /// ASCII whitespace is ignored in base64.
+    /// Returns `Err` if the body is not valid base64.
+    pub fn decode_to_vec(&self) -> Result<Vec<u8>, ()> {
+        let bytes: Vec<u8> = percent_decode(self.body.as_bytes()).collect();
+        if !self.base64 {
+            return Ok(bytes)
+        }
+        let base64: Vec<u8> = bytes.into_iter().filter(|&b| !is_ascii_whitespace(b)).collect();
+        base64.from_base64().map_err(|_| ())
+    }
+}
+
+
+fn is_ascii_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | b'\x0C')
+}
+
+fn trim_ascii_whitespace(input: &str) -> &str {
+    input.trim_matches(|c: char| c.is_ascii() && is_ascii_whitespace(c as u8))
+}
@@ -79,18 +79,6 @@ impl EncodingOverride {
         EncodingOverride
     }
 
-    pub fn lookup(_label: &[u8]) -> Option<EncodingOverride> {
-        None
-    }
-
-    pub fn is_utf8(&self) -> bool {
-        true
-    }
-
-    pub fn decode(&self, input: &[u8]) -> String {
-        String::from_utf8_lossy(input).into_owned()
-    }
-
     pub fn encode<'a>(&self, input: &'a str) -> Cow<'a, [u8]> {
         Cow::Borrowed(input.as_bytes())
     }
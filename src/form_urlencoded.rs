@@ -13,10 +13,10 @@
 //! Converts between a string (such as an URL’s query string)
 //! and a sequence of (name, value) pairs.
 
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::ascii::AsciiExt;
 use encoding::EncodingOverride;
-use percent_encoding::{percent_encode_to, percent_decode, FORM_URLENCODED_ENCODE_SET};
+use percent_encoding::{percent_encode_to, percent_decode, EncodeSet, FORM_URLENCODED_ENCODE_SET};
 
 
 /// Convert a byte string in the `application/x-www-form-urlencoded` format
@@ -28,7 +28,112 @@ use percent_encoding::{percent_encode_to, percent_decode, FORM_URLENCODED_ENCODE
 /// converted to `[("#first", "%try%")]`.
 #[inline]
 pub fn parse(input: &[u8]) -> Vec<(String, String)> {
-    parse_internal(input, EncodingOverride::utf8(), false).unwrap()
+    Parser::new(input).map(|(name, value)| (name.into_owned(), value.into_owned())).collect()
+}
+
+
+/// Like `parse`, but with pairs separated by `separator` instead of `&`,
+/// for example `;` in `a=1;b=2`.
+///
+/// # Panics
+///
+/// Panics if `separator` is not an ASCII character.
+#[inline]
+pub fn parse_with_separator(input: &[u8], separator: char) -> Vec<(String, String)> {
+    Parser::with_separator(input, separator)
+        .map(|(name, value)| (name.into_owned(), value.into_owned())).collect()
+}
+
+
+/// An iterator of the (name, value) pairs of a byte string
+/// in the `application/x-www-form-urlencoded` format.
+///
+/// Unlike `parse`, this does not allocate for the whole input:
+/// each pair is split and decoded as the iterator advances,
+/// and names and values are borrowed from the input if they contain no `+` or `%` escape.
+///
+/// ```rust
+/// use url::form_urlencoded::Parser;
+///
+/// let body = b"id=42&name=Jane+Doe&bio=...";
+/// let name = Parser::new(body).find(|&(ref name, _)| name == "name").map(|(_, value)| value);
+/// assert_eq!(name.unwrap(), "Jane Doe");
+/// ```
+#[derive(Clone)]
+pub struct Parser<'a> {
+    input: &'a [u8],
+    separator: u8,
+}
+
+impl<'a> Parser<'a> {
+    /// Return an iterator of the (name, value) pairs of `input`.
+    ///
+    /// Use `Parser::new(input.as_bytes())` to parse a `&str` string.
+    #[inline]
+    pub fn new(input: &'a [u8]) -> Parser<'a> {
+        Parser { input: input, separator: b'&' }
+    }
+
+    /// Return an iterator of the (name, value) pairs of `input`,
+    /// with pairs separated by `separator` instead of `&`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `separator` is not an ASCII character.
+    #[inline]
+    pub fn with_separator(input: &'a [u8], separator: char) -> Parser<'a> {
+        Parser { input: input, separator: ascii_separator(separator) }
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = (Cow<'a, str>, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.input.is_empty() {
+                return None
+            }
+            let separator = self.separator;
+            let mut split = self.input.splitn(2, |&b| b == separator);
+            let piece = split.next().unwrap();
+            self.input = split.next().unwrap_or(&[][..]);
+            if piece.is_empty() {
+                continue
+            }
+            let mut split = piece.splitn(2, |&b| b == b'=');
+            let name = split.next().unwrap();
+            let value = split.next().unwrap_or(&[][..]);
+            return Some((decode(name), decode(value)))
+        }
+    }
+}
+
+fn ascii_separator(separator: char) -> u8 {
+    assert!((separator as u32) < 0x80, "form_urlencoded separator {:?} is not ASCII", separator);
+    separator as u8
+}
+
+fn decode(input: &[u8]) -> Cow<str> {
+    if input.contains(&b'+') {
+        let replaced: Vec<u8> = input.iter().map(|&b| if b == b'+' { b' ' } else { b }).collect();
+        Cow::Owned(percent_decode(&replaced).decode_utf8_lossy().into_owned())
+    } else {
+        percent_decode(input).decode_utf8_lossy()
+    }
+}
+
+
+/// Like `parse`, but keep the empty pairs that `parse` skips,
+/// between consecutive `&` separators or at either end of a non-empty input, as `None`.
+///
+/// For example, `&&a=1&` is converted to `[None, None, Some(("a", "1")), None]`.
+/// This can be used to reject input with stray separators.
+pub fn parse_preserving_empty_pairs(input: &[u8]) -> Vec<Option<(String, String)>> {
+    if input.is_empty() {
+        return Vec::new()
+    }
+    input.split(|&b| b == b'&').map(|piece| parse(piece).pop()).collect()
 }
 
 
@@ -44,6 +149,9 @@ pub fn parse(input: &[u8]) -> Vec<(String, String)> {
 /// * `encoding_override`: The character encoding each name and values is decoded as
 ///    after percent-decoding. Defaults to UTF-8.
 /// * `use_charset`: The *use _charset_ flag*. If in doubt, set to `false`.
+///
+/// Returns `None` if the encoding is not UTF-8 and the input contains non-ASCII bytes.
+/// Such bytes can not occur in a correctly percent-encoded form submission.
 #[cfg(feature = "query_encoding")]
 #[inline]
 pub fn parse_with_encoding(input: &[u8], encoding_override: Option<::encoding::EncodingRef>,
@@ -53,6 +161,7 @@ pub fn parse_with_encoding(input: &[u8], encoding_override: Option<::encoding::E
 }
 
 
+#[cfg(feature = "query_encoding")]
 fn parse_internal(input: &[u8], mut encoding_override: EncodingOverride, mut use_charset: bool)
                   -> Option<Vec<(String, String)>> {
     let mut pairs = Vec::new();
@@ -84,8 +193,8 @@ fn parse_internal(input: &[u8], mut encoding_override: EncodingOverride, mut use
     }
 
     Some(pairs.into_iter().map(|(name, value)| (
-        encoding_override.decode(&percent_decode(&name)),
-        encoding_override.decode(&percent_decode(&value))
+        encoding_override.decode(&Cow::from(percent_decode(&name))),
+        encoding_override.decode(&Cow::from(percent_decode(&value)))
     )).collect())
 }
 
@@ -95,7 +204,21 @@ fn parse_internal(input: &[u8], mut encoding_override: EncodingOverride, mut use
 #[inline]
 pub fn serialize<I, K, V>(pairs: I) -> String
 where I: IntoIterator, I::Item: Borrow<(K, V)>, K: AsRef<str>, V: AsRef<str> {
-    serialize_internal(pairs, EncodingOverride::utf8())
+    serialize_internal(pairs, EncodingOverride::utf8(), b'&')
+}
+
+/// Like `serialize`, but with pairs separated by `separator` instead of `&`,
+/// for example `;` in `a=1;b=2`.
+///
+/// Occurrences of `separator` in names and values are percent-encoded.
+///
+/// # Panics
+///
+/// Panics if `separator` is not an ASCII character.
+#[inline]
+pub fn serialize_with_separator<I, K, V>(pairs: I, separator: char) -> String
+where I: IntoIterator, I::Item: Borrow<(K, V)>, K: AsRef<str>, V: AsRef<str> {
+    serialize_internal(pairs, EncodingOverride::utf8(), ascii_separator(separator))
 }
 
 /// Convert an iterator of (name, value) pairs
@@ -113,32 +236,34 @@ pub fn serialize_with_encoding<I, K, V>(pairs: I,
                                         encoding_override: Option<::encoding::EncodingRef>)
                                         -> String
 where I: IntoIterator, I::Item: Borrow<(K, V)>, K: AsRef<str>, V: AsRef<str> {
-    serialize_internal(pairs, EncodingOverride::from_opt_encoding(encoding_override))
+    serialize_internal(pairs, EncodingOverride::from_opt_encoding(encoding_override), b'&')
 }
 
-fn serialize_internal<I, K, V>(pairs: I, encoding_override: EncodingOverride) -> String
+fn serialize_internal<I, K, V>(pairs: I, encoding_override: EncodingOverride, separator: u8)
+                               -> String
 where I: IntoIterator, I::Item: Borrow<(K, V)>, K: AsRef<str>, V: AsRef<str> {
     #[inline]
     fn byte_serialize(input: &str, output: &mut String,
-                      encoding_override: EncodingOverride) {
+                      encoding_override: EncodingOverride, encode_set: EncodeSet) {
         for &byte in encoding_override.encode(input).iter() {
             if byte == b' ' {
                 output.push_str("+")
             } else {
-                percent_encode_to(&[byte], FORM_URLENCODED_ENCODE_SET, output)
+                percent_encode_to(&[byte], encode_set, output)
             }
         }
     }
 
+    let encode_set = FORM_URLENCODED_ENCODE_SET.add(separator);
     let mut output = String::new();
     for pair in pairs {
         let &(ref name, ref value) = pair.borrow();
         if !output.is_empty() {
-            output.push_str("&");
+            output.push(separator as char);
         }
-        byte_serialize(name.as_ref(), &mut output, encoding_override);
+        byte_serialize(name.as_ref(), &mut output, encoding_override, encode_set);
         output.push_str("=");
-        byte_serialize(value.as_ref(), &mut output, encoding_override);
+        byte_serialize(value.as_ref(), &mut output, encoding_override, encode_set);
     }
     output
 }
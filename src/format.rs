@@ -13,7 +13,9 @@
 //! You can use `<formatter>.to_string()`, as the formatters implement `fmt::Display`.
 
 use std::fmt::{self, Formatter};
-use super::Url;
+use super::{Url, SchemeData, Host};
+use idna::domain_to_unicode;
+use percent_encoding::lossy_utf8_percent_decode;
 
 /// Formatter and serializer for URL path data.
 pub struct PathFormatter<'a, T:'a> {
@@ -79,3 +81,55 @@ impl<'a> fmt::Display for UrlNoFragmentFormatter<'a> {
         Ok(())
     }
 }
+
+
+/// Formatter for the human-readable form of URLs:
+/// the domain is converted to Unicode and the path, query string
+/// and fragment identifier are percent-decoded.
+///
+/// The output is meant for display only,
+/// and may not parse back to the same URL.
+pub struct UrlHumanFormatter<'a> {
+    pub url: &'a Url
+}
+
+impl<'a> fmt::Display for UrlHumanFormatter<'a> {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        try!(formatter.write_str(&self.url.scheme));
+        try!(formatter.write_str(":"));
+        match self.url.scheme_data {
+            SchemeData::Relative(ref scheme_data) => {
+                try!(formatter.write_str("//"));
+                try!(UserInfoFormatter {
+                    username: &scheme_data.username,
+                    password: scheme_data.password.as_ref().map(|s| s as &str)
+                }.fmt(formatter));
+                match scheme_data.host {
+                    Host::Domain(ref domain) => {
+                        try!(formatter.write_str(&domain_to_unicode(domain).0))
+                    },
+                    ref host => try!(host.fmt(formatter)),
+                }
+                if let Some(port) = scheme_data.port {
+                    try!(write!(formatter, ":{}", port));
+                }
+                let path: Vec<String> = scheme_data.path.iter()
+                    .map(|path_part| lossy_utf8_percent_decode(path_part.as_bytes()))
+                    .collect();
+                try!(PathFormatter { path: &path }.fmt(formatter));
+            },
+            SchemeData::NonRelative(ref scheme_data) => {
+                try!(formatter.write_str(&lossy_utf8_percent_decode(scheme_data.as_bytes())));
+            },
+        }
+        if let Some(ref query) = self.url.query {
+            try!(formatter.write_str("?"));
+            try!(formatter.write_str(&lossy_utf8_percent_decode(query.as_bytes())));
+        }
+        if let Some(ref fragment) = self.url.fragment {
+            try!(formatter.write_str("#"));
+            try!(formatter.write_str(&lossy_utf8_percent_decode(fragment.as_bytes())));
+        }
+        Ok(())
+    }
+}
@@ -9,10 +9,15 @@
 use std::ascii::AsciiExt;
 use std::cmp;
 use std::fmt::{self, Formatter};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::io;
+use std::str;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 use parser::{ParseResult, ParseError};
+use super::IdnaProcessing;
 use percent_encoding::{from_hex, percent_decode};
 use idna;
+#[cfg(feature="serde_serialization")]
+use serde;
 
 
 /// The host name of an URL.
@@ -27,53 +32,141 @@ pub enum Host {
     /// so that `:` colon characters in the address are not ambiguous
     /// with the port number delimiter.
     Ipv6(Ipv6Addr),
+    /// An RFC 3986 *IPvFuture* address such as `v1.fe80::a`,
+    /// represented inside `[...]` square brackets like an IPv6 address.
+    /// The string is the text between the brackets, as it was written.
+    ///
+    /// URLs in a *special* scheme such as `http` can not have this kind of host.
+    IpvFuture(String),
 }
 
 
 impl Host {
-    /// Parse a host: either an IPv6 address in [] square brackets, or a domain.
+    /// Parse a host: either an IPv6 or IPvFuture address in [] square brackets, or a domain.
+    ///
+    /// An IPv6 address without square brackets (as from `Ipv6Addr::to_string`)
+    /// is also accepted, since `:` colons are never valid in a domain.
     ///
     /// Returns `Err` for an empty host, an invalid IPv6 address,
     /// or a or invalid non-ASCII domain.
+    ///
+    /// Non-ASCII domains are converted with `IdnaProcessing::Transitional`.
+    ///
+    /// Any input that the URL Standard’s IPv4 parser accepts is returned as `Host::Ipv4`,
+    /// never as a domain. This includes hexadecimal and octal numbers,
+    /// and fewer than four parts: `0x7f.1`, `0177.0.0.1`, and `2130706433`
+    /// all give the same host as `127.0.0.1`.
     pub fn parse(input: &str) -> ParseResult<Host> {
-        if input.len() == 0 {
-            return Err(ParseError::EmptyHost)
+        parse(input, IdnaProcessing::Transitional)
+    }
+
+    /// Serialize the host as a string.
+    ///
+    /// A domain a returned as-is, an IPv6 address between [] square brackets.
+    pub fn serialize(&self) -> String {
+        self.to_string()
+    }
+
+    /// If the host is an IPv4 or IPv6 address, return it.
+    ///
+    /// Returns `None` for domains (which would need to be resolved)
+    /// and for IPvFuture addresses.
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        match *self {
+            Host::Ipv4(address) => Some(IpAddr::V4(address)),
+            Host::Ipv6(address) => Some(IpAddr::V6(address)),
+            Host::Domain(_) | Host::IpvFuture(_) => None,
         }
-        if input.starts_with("[") {
-            if !input.ends_with("]") {
-                return Err(ParseError::InvalidIpv6Address)
-            }
-            return parse_ipv6addr(&input[1..input.len() - 1]).map(Host::Ipv6)
+    }
+}
+
+
+const FORBIDDEN_DOMAIN_CHARACTERS: &'static [char] = &[
+    '\0', '\t', '\n', '\r', ' ', '#', '%', '/', ':', '?', '@', '[', '\\', ']'
+];
+
+
+/// Parse a host like `Host::parse` does, with the given processing of non-ASCII domains.
+pub fn parse(input: &str, idna_processing: IdnaProcessing) -> ParseResult<Host> {
+    if input.len() == 0 {
+        return Err(ParseError::EmptyHost)
+    }
+    if input.starts_with("[") {
+        if !input.ends_with("]") {
+            return Err(ParseError::InvalidIpv6Address)
+        }
+        let address = &input[1..input.len() - 1];
+        if address.starts_with("v") || address.starts_with("V") {
+            return parse_ipv_future(address).map(Host::IpvFuture)
+        }
+        return parse_ipv6addr(address).map(Host::Ipv6)
+    }
+    if input.contains(':') {
+        if let Ok(address) = parse_ipv6addr(input) {
+            return Ok(Host::Ipv6(address))
         }
-        let decoded = percent_decode(input.as_bytes());
-        let domain = String::from_utf8_lossy(&decoded);
+    }
+    let domain = percent_decode(input.as_bytes()).decode_utf8_lossy();
+
+    let domain = match idna::uts46_to_ascii(&domain, idna::Uts46Flags {
+        use_std3_ascii_rules: false,
+        transitional_processing: idna_processing == IdnaProcessing::Transitional,
+        verify_dns_length: false,
+    }) {
+        Ok(s) => s,
+        Err(errors) => return Err(ParseError::IdnaError(errors[0]))
+    };
+
+    if domain.find(FORBIDDEN_DOMAIN_CHARACTERS).is_some() {
+        return Err(ParseError::InvalidDomainCharacter)
+    }
+    match parse_ipv4addr(&domain[..]) {
+        Ok(Some(ipv4addr)) => Ok(Host::Ipv4(ipv4addr)),
+        Ok(None) => Ok(Host::Domain(domain.to_ascii_lowercase())),
+        Err(e) => Err(e),
+    }
+}
 
-        let domain = match idna::domain_to_ascii(&domain) {
-            Ok(s) => s,
-            Err(_) => return Err(ParseError::InvalidDomainCharacter)
-        };
 
-        if domain.find(&[
-            '\0', '\t', '\n', '\r', ' ', '#', '%', '/', ':', '?', '@', '[', '\\', ']'
-        ][..]).is_some() {
-            return Err(ParseError::InvalidDomainCharacter)
+/// Return whether `host` has the syntax of a host returned by `parse`.
+///
+/// Domains may be empty, as in `file` URLs, and are not checked against IDNA.
+pub fn is_parsed_host(host: &Host) -> bool {
+    match *host {
+        Host::Domain(ref domain) => {
+            domain.is_ascii()
+            && !domain.contains(|c| matches!(c, 'A'...'Z'))
+            && domain.find(FORBIDDEN_DOMAIN_CHARACTERS).is_none()
+            && (domain.is_empty() || matches!(parse_ipv4addr(domain), Ok(None)))
         }
-        match parse_ipv4addr(&domain[..]) {
-            Ok(Some(ipv4addr)) => Ok(Host::Ipv4(ipv4addr)),
-            Ok(None) => Ok(Host::Domain(domain.to_ascii_lowercase())),
-            Err(e) => Err(e),
+        Host::IpvFuture(ref address) => {
+            (address.starts_with("v") || address.starts_with("V"))
+            && parse_ipv_future(address).is_ok()
         }
+        Host::Ipv4(..) | Host::Ipv6(..) => true,
     }
+}
 
-    /// Serialize the host as a string.
-    ///
-    /// A domain a returned as-is, an IPv6 address between [] square brackets.
-    pub fn serialize(&self) -> String {
-        self.to_string()
+
+impl str::FromStr for Host {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> ParseResult<Host> {
+        Host::parse(input)
     }
 }
 
 
+/// Write the host as in an URL: a domain as-is, an IPv4 address in dotted decimal,
+/// and an IPv6 or IPvFuture address between [] square brackets.
+///
+/// ```rust
+/// use std::net::Ipv6Addr;
+/// use url::Host;
+///
+/// let host = Host::Ipv6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+/// assert_eq!(format!("Host: {}:{}", host, 8080), "Host: [2001:db8::1]:8080");
+/// ```
 impl fmt::Display for Host {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match *self {
@@ -84,10 +177,46 @@ impl fmt::Display for Host {
                 try!(write_ipv6(addr, f));
                 f.write_str("]")
             }
+            Host::IpvFuture(ref address) => write!(f, "[{}]", address),
         }
     }
 }
 
+/// Serializes this host into a `serde` stream, as a string in the same format as `Display`.
+///
+/// This implementation is only available if the `serde_serialization` Cargo feature is enabled.
+#[cfg(feature="serde_serialization")]
+impl serde::Serialize for Host {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error> where S: serde::Serializer {
+        self.to_string().serialize(serializer)
+    }
+}
+
+/// Deserializes this host from a `serde` stream, with `Host::parse`.
+///
+/// This implementation is only available if the `serde_serialization` Cargo feature is enabled.
+#[cfg(feature="serde_serialization")]
+impl serde::Deserialize for Host {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Host, D::Error> where D: serde::Deserializer {
+        use serde::de::Error;
+        let string_representation: String = try!(serde::Deserialize::deserialize(deserializer));
+        Host::parse(&string_representation).map_err(|error| D::Error::custom(error.to_string()))
+    }
+}
+
+/// Resolve a host and port number to socket addresses.
+///
+/// IP addresses are used directly, domains go through the system resolver.
+pub fn socket_addrs(host: &Host, port: u16) -> io::Result<Vec<SocketAddr>> {
+    match *host {
+        Host::Domain(ref domain) => Ok(try!((&**domain, port).to_socket_addrs()).collect()),
+        Host::Ipv4(addr) => Ok(vec![SocketAddr::V4(SocketAddrV4::new(addr, port))]),
+        Host::Ipv6(addr) => Ok(vec![SocketAddr::V6(SocketAddrV6::new(addr, port, 0, 0))]),
+        Host::IpvFuture(_) => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                 "IPvFuture addresses are not supported")),
+    }
+}
+
 fn write_ipv6(addr: &Ipv6Addr, f: &mut Formatter) -> fmt::Result {
     let segments = addr.segments();
     let (compress_start, compress_end) = longest_zero_sequence(&segments);
@@ -143,7 +272,8 @@ fn longest_zero_sequence(pieces: &[u16; 8]) -> (isize, isize) {
 }
 
 
-fn parse_ipv4number(mut input: &str) -> ParseResult<u32> {
+/// Return `Err` if `input` is not a number, or `Ok(None)` if it does not fit in 32 bits.
+fn parse_ipv4number(mut input: &str) -> Result<Option<u32>, ()> {
     let mut r = 10;
     if input.starts_with("0x") || input.starts_with("0X") {
         input = &input[2..];
@@ -153,15 +283,12 @@ fn parse_ipv4number(mut input: &str) -> ParseResult<u32> {
         r = 8;
     }
     if input.is_empty() {
-        return Ok(0);
-    }
-    if input.starts_with("+") {
-        return Err(ParseError::InvalidIpv4Address)
+        return Ok(Some(0));
     }
-    match u32::from_str_radix(&input, r) {
-        Ok(number) => Ok(number),
-        Err(_) => Err(ParseError::InvalidIpv4Address),
+    if !input.chars().all(|c| c.is_digit(r)) {
+        return Err(())
     }
+    Ok(u32::from_str_radix(&input, r).ok())
 }
 
 fn parse_ipv4addr(input: &str) -> ParseResult<Option<Ipv4Addr>> {
@@ -173,16 +300,20 @@ fn parse_ipv4addr(input: &str) -> ParseResult<Option<Ipv4Addr>> {
         return Ok(None);
     }
     let mut numbers: Vec<u32> = Vec::new();
+    let mut overflow = false;
     for part in parts {
         if part == "" {
             return Ok(None);
         }
-        if let Ok(n) = parse_ipv4number(part) {
-            numbers.push(n);
-        } else {
-            return Ok(None);
+        match parse_ipv4number(part) {
+            Ok(Some(n)) => numbers.push(n),
+            Ok(None) => overflow = true,
+            Err(()) => return Ok(None),
         }
     }
+    if overflow {
+        return Err(ParseError::InvalidIpv4Address);
+    }
     let mut ipv4 = numbers.pop().expect("a non-empty list of numbers");
     // Equivalent to: ipv4 >= 256 ** (4 − numbers.len())
     if ipv4 > u32::max_value() >> (8 * numbers.len() as u32)  {
@@ -198,6 +329,27 @@ fn parse_ipv4addr(input: &str) -> ParseResult<Option<Ipv4Addr>> {
 }
 
 
+/// https://tools.ietf.org/html/rfc3986#section-3.2.2
+///
+/// IPvFuture = "v" 1*HEXDIG "." 1*( unreserved / sub-delims / ":" )
+fn parse_ipv_future(input: &str) -> ParseResult<String> {
+    let (version, address) = match input[1..].find('.') {
+        Some(dot) => (&input[1..dot + 1], &input[dot + 2..]),
+        None => return Err(ParseError::InvalidIpvFutureAddress),
+    };
+    if version.is_empty() || !version.bytes().all(|b| from_hex(b).is_some()) {
+        return Err(ParseError::InvalidIpvFutureAddress)
+    }
+    if address.is_empty() || !address.bytes().all(|b| matches!(b,
+        b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' |
+        b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' | b':'
+    )) {
+        return Err(ParseError::InvalidIpvFutureAddress)
+    }
+    Ok(input.to_owned())
+}
+
+
 fn parse_ipv6addr(input: &str) -> ParseResult<Ipv6Addr> {
     let input = input.as_bytes();
     let len = input.len();
@@ -205,7 +205,6 @@ fn validate(label: &str, flags: Uts46Flags, errors: &mut Vec<Error>) {
             Mapping::DisallowedStd3Valid => flags.use_std3_ascii_rules,
             _ => true,
         })
-        || !passes_bidi(label, flags.transitional_processing)
     {
         errors.push(Error::ValidityCriteria)
     }
@@ -213,28 +212,49 @@ fn validate(label: &str, flags: Uts46Flags, errors: &mut Vec<Error>) {
 
 /// http://www.unicode.org/reports/tr46/#Processing
 fn uts46_processing(domain: &str, flags: Uts46Flags, errors: &mut Vec<Error>) -> String {
-    let mut mapped = String::new();
-    for c in domain.chars() {
-        map_char(c, flags, &mut mapped, errors)
-    }
-    let normalized: String = mapped.nfc().collect();
+    let normalized: String = if domain.is_ascii() && !flags.use_std3_ascii_rules {
+        // Fast path: without STD3 rules, ASCII code points are either valid
+        // or mapped to their lower case, and ASCII strings are already in NFC.
+        domain.to_ascii_lowercase()
+    } else {
+        let mut mapped = String::new();
+        for c in domain.chars() {
+            map_char(c, flags, &mut mapped, errors)
+        }
+        mapped.nfc().collect()
+    };
     let mut validated = String::new();
+    let mut labels = Vec::new();
     for label in normalized.split('.') {
         if validated.len() > 0 {
             validated.push('.');
         }
+        let start = validated.len();
         if label.starts_with("xn--") {
+            let flags = Uts46Flags { transitional_processing: false, ..flags };
             match punycode::decode_to_string(&label["xn--".len()..]) {
                 Some(decoded_label) => {
-                    let flags = Uts46Flags { transitional_processing: false, ..flags };
                     validate(&decoded_label, flags, errors);
                     validated.push_str(&decoded_label)
                 }
                 None => errors.push(Error::PunycodeError)
             }
+            labels.push((start, validated.len(), flags))
         } else {
             validate(label, flags, errors);
-            validated.push_str(label)
+            validated.push_str(label);
+            labels.push((start, validated.len(), flags))
+        }
+    }
+
+    // http://tools.ietf.org/html/rfc5893#section-1.4
+    // The bidi rule applies to every label, but only in a domain with right-to-left characters.
+    if validated.chars().any(|c| matches!(bidi_class(c),
+                                          BidiClass::R | BidiClass::AL | BidiClass::AN)) {
+        for &(start, end, flags) in &labels {
+            if !passes_bidi(&validated[start..end], flags.transitional_processing) {
+                errors.push(Error::ValidityCriteria)
+            }
         }
     }
     validated
@@ -247,6 +267,7 @@ pub struct Uts46Flags {
    pub verify_dns_length: bool,
 }
 
+/// Errors that can occur when converting a domain to ASCII or Unicode.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Error {
     PunycodeError,
@@ -8,13 +8,16 @@
 
 use std::ascii::AsciiExt;
 use std::cmp::max;
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::{self, Formatter};
 
-use super::{UrlParser, Url, SchemeData, RelativeSchemeData, Host, SchemeType};
+use super::{UrlParser, Url, SchemeData, RelativeSchemeData, Host, SchemeType, is_special_scheme};
+use idna;
 use percent_encoding::{
-    utf8_percent_encode_to, percent_encode,
-    SIMPLE_ENCODE_SET, DEFAULT_ENCODE_SET, USERINFO_ENCODE_SET, QUERY_ENCODE_SET
+    utf8_percent_encode_to, percent_encode, percent_decode, EncodeSet,
+    SIMPLE_ENCODE_SET, DEFAULT_ENCODE_SET, USERINFO_ENCODE_SET, QUERY_ENCODE_SET,
+    FRAGMENT_ENCODE_SET, USERNAME_ENCODE_SET, PASSWORD_ENCODE_SET
 };
 
 
@@ -22,13 +25,17 @@ pub type ParseResult<T> = Result<T, ParseError>;
 
 
 macro_rules! simple_enum_error {
-    ($($name: ident => $description: expr,)+) => {
+    ($($name: ident => $description: expr,)+
+     ; $($data_name: ident($data: ty) => $data_description: expr,)*) => {
         /// Errors that can occur during parsing.
         #[derive(PartialEq, Eq, Clone, Copy, Debug)]
         pub enum ParseError {
             $(
                 $name,
             )+
+            $(
+                $data_name($data),
+            )*
         }
 
         impl Error for ParseError {
@@ -37,6 +44,9 @@ macro_rules! simple_enum_error {
                     $(
                         ParseError::$name => $description,
                     )+
+                    $(
+                        ParseError::$data_name(..) => $data_description,
+                    )*
                 }
             }
         }
@@ -49,6 +59,7 @@ simple_enum_error! {
     InvalidPort => "invalid port number",
     InvalidIpv4Address => "invalid IPv4 address",
     InvalidIpv6Address => "invalid IPv6 address",
+    InvalidIpvFutureAddress => "invalid IPvFuture address",
     InvalidDomainCharacter => "invalid domain character",
     InvalidCharacter => "invalid character",
     InvalidBackslash => "invalid backslash",
@@ -60,15 +71,24 @@ simple_enum_error! {
     RelativeUrlWithScheme => "relative URL with scheme",
     RelativeUrlWithoutBase => "relative URL without a base",
     RelativeUrlWithNonRelativeBase => "relative URL with a non-relative base",
+    DuplicateQueryKey => "duplicate name in query string",
+    InvalidUrlTemplate => "invalid URL template",
+    TooManyPathSegments => "too many path segments",
+    UrlTooLong => "URL too long",
     NonAsciiDomainsNotSupportedYet => "non-ASCII domains are not supported yet",
     CannotSetJavascriptFragment => "cannot set fragment on javascript: URL",
     CannotSetPortWithFileLikeScheme => "cannot set port with file-like scheme",
+    CannotSetUsernameWithFileLikeScheme => "cannot set username with file-like scheme",
+    CannotSetPasswordWithFileLikeScheme => "cannot set password with file-like scheme",
     CannotSetUsernameWithNonRelativeScheme => "cannot set username with non-relative scheme",
     CannotSetPasswordWithNonRelativeScheme => "cannot set password with non-relative scheme",
     CannotSetHostPortWithNonRelativeScheme => "cannot set host and port with non-relative scheme",
     CannotSetHostWithNonRelativeScheme => "cannot set host with non-relative scheme",
     CannotSetPortWithNonRelativeScheme => "cannot set port with non-relative scheme",
     CannotSetPathWithNonRelativeScheme => "cannot set path with non-relative scheme",
+    ;
+    // The first error found while converting a non-ASCII domain to ASCII.
+    IdnaError(idna::Error) => "invalid international domain name",
 }
 
 impl fmt::Display for ParseError {
@@ -77,6 +97,51 @@ impl fmt::Display for ParseError {
     }
 }
 
+/// A parse error, together with where in the input it was found.
+///
+/// See the `UrlParser::parse_located` method.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ParseErrorAt {
+    /// The kind of error.
+    pub kind: ParseError,
+
+    /// The byte index in the input where the error was found.
+    pub offset: usize,
+
+    /// The length in bytes of the input text that caused the error, starting at `offset`,
+    /// or zero if the error is not attributed to a specific text.
+    ///
+    /// For `InvalidPort`, this is the whole port number as written, such as `99999`.
+    pub len: usize,
+
+    /// The URL component being parsed when the error was found:
+    /// one of `"scheme"`, `"authority"`, `"userinfo"`, `"host"`, `"port"`, `"path"`,
+    /// `"scheme data"`, `"query"`, or `"fragment"`.
+    pub component: &'static str,
+}
+
+impl ParseErrorAt {
+    /// Return the text that caused the error, given the input that was parsed.
+    ///
+    /// Returns the empty string if the error is not attributed to a specific text.
+    #[inline]
+    pub fn text<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.offset..self.offset + self.len]
+    }
+}
+
+impl Error for ParseErrorAt {
+    fn description(&self) -> &str {
+        self.kind.description()
+    }
+}
+
+impl fmt::Display for ParseErrorAt {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{} in {} at byte {}", self.kind, self.component, self.offset)
+    }
+}
+
 /// This is called on non-fatal parse errors.
 ///
 /// The handler can choose to continue or abort parsing by returning Ok() or Err(), respectively.
@@ -86,6 +151,54 @@ impl fmt::Display for ParseError {
 pub type ErrorHandler = fn(reason: ParseError) -> ParseResult<()>;
 
 
+/// The result of the parsing functions below, which is converted to `ParseResult` by `try!`.
+pub type LocatedResult<T> = Result<T, LocatedError>;
+
+/// A parse error together with where it was found, counted from the end of the input
+/// so that it can be computed from any suffix of the input.
+#[derive(Clone, Copy, Debug)]
+pub struct LocatedError {
+    kind: ParseError,
+    /// The length of the input from where the error was found to its end.
+    remaining: usize,
+    len: usize,
+    component: &'static str,
+}
+
+impl LocatedError {
+    /// An error caused by the first `len` bytes of `at`, a suffix of the input.
+    #[inline]
+    pub fn new(kind: ParseError, at: &str, len: usize, component: &'static str) -> LocatedError {
+        LocatedError { kind: kind, remaining: at.len(), len: len, component: component }
+    }
+
+    /// Make an error found in a prefix of the input relative to the whole input,
+    /// given the length of the rest of the input after that prefix.
+    #[inline]
+    pub fn followed_by(self, len: usize) -> LocatedError {
+        LocatedError { remaining: self.remaining + len, ..self }
+    }
+
+    /// Return the offset of the error in `input`, the whole parsed input.
+    #[inline]
+    pub fn in_input(self, input: &str) -> ParseErrorAt {
+        ParseErrorAt {
+            kind: self.kind,
+            offset: input.len() - self.remaining,
+            len: self.len,
+            component: self.component,
+        }
+    }
+}
+
+impl From<LocatedError> for ParseError {
+    #[inline]
+    fn from(error: LocatedError) -> ParseError {
+        error.kind
+    }
+}
+
+
 #[derive(PartialEq, Eq)]
 pub enum Context {
     UrlParser,
@@ -93,8 +206,20 @@ pub enum Context {
 }
 
 
-pub fn parse_url(input: &str, parser: &UrlParser) -> ParseResult<Url> {
-    let input = input.trim_matches(&[' ', '\t', '\n', '\r', '\x0C'][..]);
+pub fn parse_url(input: &str, parser: &UrlParser) -> LocatedResult<Url> {
+    if let Some(max_length) = parser.max_length {
+        if input.len() > max_length {
+            return parser.fatal_error(ParseError::UrlTooLong, input, "scheme")
+        }
+    }
+    let whitespace = &[' ', '\t', '\n', '\r', '\x0C'][..];
+    let trailing_whitespace = input.len() - input.trim_right_matches(whitespace).len();
+    parse_trimmed_url(input.trim_matches(whitespace), parser)
+        .map_err(|error| error.followed_by(trailing_whitespace))
+}
+
+
+fn parse_trimmed_url(input: &str, parser: &UrlParser) -> LocatedResult<Url> {
     let (scheme, remaining) = match parse_scheme(input, Context::UrlParser) {
         Some((scheme, remaining)) => (scheme, remaining),
         // No-scheme state
@@ -104,8 +229,9 @@ pub fn parse_url(input: &str, parser: &UrlParser) -> ParseResult<Url> {
                 let scheme_type = parser.get_scheme_type(&scheme);
                 parse_relative_url(input, scheme.clone(), scheme_type, base, query, parser)
             },
-            Some(_) => Err(ParseError::RelativeUrlWithNonRelativeBase),
-            None => Err(ParseError::RelativeUrlWithoutBase),
+            Some(_) => parser.fatal_error(
+                ParseError::RelativeUrlWithNonRelativeBase, input, "scheme"),
+            None => parser.fatal_error(ParseError::RelativeUrlWithoutBase, input, "scheme"),
         },
     };
     let scheme_type = parser.get_scheme_type(&scheme);
@@ -130,7 +256,8 @@ pub fn parse_url(input: &str, parser: &UrlParser) -> ParseResult<Url> {
                 Some(&Url { scheme: ref base_scheme, scheme_data: SchemeData::Relative(ref base),
                             ref query, .. })
                 if scheme == *base_scheme && !remaining.starts_with("//") => {
-                    try!(parser.parse_error(ParseError::RelativeUrlWithScheme));
+                    try!(parser.parse_error(
+                        ParseError::RelativeUrlWithScheme, remaining, "scheme"));
                     parse_relative_url(remaining, scheme, scheme_type, base, query, parser)
                 },
                 _ => parse_absolute_url(scheme, scheme_type, remaining, parser),
@@ -170,13 +297,14 @@ pub fn parse_scheme(input: &str, context: Context) -> Option<(String, &str)> {
 
 
 fn parse_absolute_url<'a>(scheme: String, scheme_type: SchemeType,
-                          input: &'a str, parser: &UrlParser) -> ParseResult<Url> {
+                          input: &'a str, parser: &UrlParser) -> LocatedResult<Url> {
     // Authority first slash state
     let remaining = try!(skip_slashes(input, parser));
     // Authority state
     let (username, password, remaining) = try!(parse_userinfo(remaining, parser));
     // Host state
-    let (host, port, default_port, remaining) = try!(parse_host(remaining, scheme_type, parser));
+    let (host, port, default_port, remaining) = try!(parse_host(
+        remaining, &scheme, scheme_type, parser));
     let (path, remaining) = try!(parse_path_start(
         remaining, Context::UrlParser, scheme_type, parser));
     let scheme_data = SchemeData::Relative(RelativeSchemeData {
@@ -191,7 +319,7 @@ fn parse_absolute_url<'a>(scheme: String, scheme_type: SchemeType,
 fn parse_relative_url<'a>(input: &'a str, scheme: String, scheme_type: SchemeType,
                           base: &RelativeSchemeData, base_query: &Option<String>,
                           parser: &UrlParser)
-                          -> ParseResult<Url> {
+                          -> LocatedResult<Url> {
     let mut chars = input.chars();
     match chars.next() {
         Some('/') | Some('\\') => {
@@ -199,7 +327,8 @@ fn parse_relative_url<'a>(input: &'a str, scheme: String, scheme_type: SchemeTyp
             // Relative slash state
             if matches!(ch, Some('/') | Some('\\')) {
                 if ch == Some('\\') {
-                    try!(parser.parse_error(ParseError::InvalidBackslash))
+                    try!(parser.parse_error(
+                        ParseError::InvalidBackslash, &input[1..], "authority"))
                 }
                 if scheme_type == SchemeType::FileLike {
                     // File host state
@@ -214,7 +343,7 @@ fn parse_relative_url<'a>(input: &'a str, scheme: String, scheme_type: SchemeTyp
                         // Windows drive letter quirk
                         (Host::Domain(String::new()), remaining)
                     } else {
-                        try!(parse_file_host(remaining, parser))
+                        try!(parse_file_host(remaining, &scheme, parser))
                     };
                     let (path, remaining) = try!(parse_path_start(
                         remaining, Context::UrlParser, scheme_type, parser));
@@ -259,7 +388,7 @@ fn parse_relative_url<'a>(input: &'a str, scheme: String, scheme_type: SchemeTyp
                      query: query, fragment: fragment })
         },
         Some('#') => {
-            let fragment = Some(try!(parse_fragment(&input[1..], parser)));
+            let fragment = Some(try!(parse_fragment(&input[1..], Context::UrlParser, parser)));
             Ok(Url { scheme: scheme, scheme_data: SchemeData::Relative(base.clone()),
                      query: base_query.clone(), fragment: fragment })
         }
@@ -307,23 +436,24 @@ fn parse_relative_url<'a>(input: &'a str, scheme: String, scheme_type: SchemeTyp
 }
 
 
-fn skip_slashes<'a>(input: &'a str, parser: &UrlParser) -> ParseResult<&'a str> {
+fn skip_slashes<'a>(input: &'a str, parser: &UrlParser) -> LocatedResult<&'a str> {
     let first_non_slash = input.find(|c| !matches!(c, '/' | '\\')).unwrap_or(input.len());
     if &input[..first_non_slash] != "//" {
-        try!(parser.parse_error(ParseError::ExpectedTwoSlashes));
+        try!(parser.parse_error(ParseError::ExpectedTwoSlashes, input, "authority"));
     }
     Ok(&input[first_non_slash..])
 }
 
 
 fn parse_userinfo<'a>(input: &'a str, parser: &UrlParser)
-                      -> ParseResult<(String, Option<String>, &'a str)> {
+                      -> LocatedResult<(String, Option<String>, &'a str)> {
     let mut last_at = None;
     for (i, c) in input.char_indices() {
         match c {
             '@' => {
                 if last_at.is_some() {
-                    try!(parser.parse_error(ParseError::InvalidAtSymbolInUser))
+                    try!(parser.parse_error(
+                        ParseError::InvalidAtSymbolInUser, &input[i..], "userinfo"))
                 }
                 last_at = Some(i)
             },
@@ -336,49 +466,72 @@ fn parse_userinfo<'a>(input: &'a str, parser: &UrlParser)
         None => return Ok((String::new(), None, input)),
     };
 
-    let mut username = String::new();
-    let mut password = None;
-    for (i, c, next_i) in input.char_ranges() {
-        match c {
-            ':' => {
-                password = Some(try!(parse_password(&input[i + 1..], parser)));
-                break
-            },
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
-            _ => {
-                try!(check_url_code_point(input, i, c, parser));
-                // The spec says to use the default encode set,
-                // but also replaces '@' by '%40' in an earlier step.
-                utf8_percent_encode_to(&input[i..next_i],
-                                    USERINFO_ENCODE_SET, &mut username);
-            }
-        }
-    }
+    let (username, password) = match input.find(':') {
+        Some(colon) => (&input[..colon], Some(&input[colon + 1..])),
+        None => (input, None),
+    };
+    // Errors in the username or password are relative to their end, not the input's.
+    let after_username = input.len() - username.len() + remaining.len() + 1;
+    let username = try!(parse_username(username, Context::UrlParser, parser)
+        .map_err(|error| error.followed_by(after_username)));
+    let password = match password {
+        Some(password) => Some(try!(parse_password(password, Context::UrlParser, parser)
+            .map_err(|error| error.followed_by(remaining.len() + 1)))),
+        None => None,
+    };
     Ok((username, password, remaining))
 }
 
 
-fn parse_password(input: &str, parser: &UrlParser) -> ParseResult<String> {
-    let mut password = String::new();
+/// Parse a username, either within an URL or as the value given to the `username` setter.
+pub fn parse_username(input: &str, context: Context, parser: &UrlParser) -> LocatedResult<String> {
+    // The spec says to use the default encode set,
+    // but also replaces '@' by '%40' in an earlier step.
+    // A setter also encodes '/', '\' and ':', which would otherwise end the username.
+    let encode_set = match context {
+        Context::UrlParser => USERINFO_ENCODE_SET,
+        Context::Setter => USERNAME_ENCODE_SET,
+    };
+    parse_userinfo_component(input, encode_set, context, parser)
+}
+
+
+/// Parse a password, either within an URL or as the value given to the `password` setter.
+pub fn parse_password(input: &str, context: Context, parser: &UrlParser) -> LocatedResult<String> {
+    let encode_set = match context {
+        Context::UrlParser => USERINFO_ENCODE_SET,
+        Context::Setter => PASSWORD_ENCODE_SET,
+    };
+    parse_userinfo_component(input, encode_set, context, parser)
+}
+
+
+fn parse_userinfo_component(input: &str, encode_set: EncodeSet, context: Context,
+                            parser: &UrlParser) -> LocatedResult<String> {
+    let mut output = String::new();
     for (i, c, next_i) in input.char_ranges() {
         match c {
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+            '\t' | '\n' | '\r' => {
+                try!(parser.parse_error(ParseError::InvalidCharacter, &input[i..], "userinfo"));
+                // The parser removes these, but a setter has nothing else that would.
+                if context == Context::Setter {
+                    utf8_percent_encode_to(&input[i..next_i], encode_set, &mut output);
+                }
+            }
             _ => {
-                try!(check_url_code_point(input, i, c, parser));
-                // The spec says to use the default encode set,
-                // but also replaces '@' by '%40' in an earlier step.
-                utf8_percent_encode_to(&input[i..next_i],
-                                    USERINFO_ENCODE_SET, &mut password);
+                try!(check_url_code_point(input, i, c, "userinfo", parser));
+                utf8_percent_encode_to(&input[i..next_i], encode_set, &mut output);
             }
         }
     }
-    Ok(password)
+    Ok(output)
 }
 
 
-pub fn parse_host<'a>(input: &'a str, scheme_type: SchemeType, parser: &UrlParser)
-                          -> ParseResult<(Host, Option<u16>, Option<u16>, &'a str)> {
-    let (host, remaining) = try!(parse_hostname(input, parser));
+pub fn parse_host<'a>(input: &'a str, scheme: &str, scheme_type: SchemeType,
+                      parser: &UrlParser)
+                      -> LocatedResult<(Host, Option<u16>, Option<u16>, &'a str)> {
+    let (host, remaining) = try!(parse_hostname(input, scheme, parser));
     let (port, default_port, remaining) = if remaining.starts_with(":") {
         try!(parse_port(&remaining[1..], scheme_type, parser))
     } else {
@@ -388,8 +541,8 @@ pub fn parse_host<'a>(input: &'a str, scheme_type: SchemeType, parser: &UrlParse
 }
 
 
-pub fn parse_hostname<'a>(input: &'a str, parser: &UrlParser)
-                      -> ParseResult<(Host, &'a str)> {
+pub fn parse_hostname<'a>(input: &'a str, scheme: &str, parser: &UrlParser)
+                      -> LocatedResult<(Host, &'a str)> {
     let mut inside_square_brackets = false;
     let mut host_input = String::new();
     let mut end = input.len();
@@ -403,7 +556,8 @@ pub fn parse_hostname<'a>(input: &'a str, parser: &UrlParser)
                 end = i;
                 break
             },
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+            '\t' | '\n' | '\r' => try!(parser.parse_error(
+                ParseError::InvalidCharacter, &input[i..], "host")),
             c => {
                 match c {
                     '[' => inside_square_brackets = true,
@@ -414,13 +568,28 @@ pub fn parse_hostname<'a>(input: &'a str, parser: &UrlParser)
             }
         }
     }
-    let host = try!(Host::parse(&host_input));
+    let host = try!(parse_url_host(&host_input, scheme, parser)
+        .or_else(|error| parser.fatal_error(error, input, "host")));
     Ok((host, &input[end..]))
 }
 
 
+/// Parse the host of an URL like `Host::parse`,
+/// except that an IPvFuture address is an invalid IPv6 address in a *special* scheme.
+fn parse_url_host(input: &str, scheme: &str, parser: &UrlParser) -> ParseResult<Host> {
+    match try!(::host::parse(input, parser.idna_processing)) {
+        Host::IpvFuture(..) if is_special_scheme(scheme) => Err(ParseError::InvalidIpv6Address),
+        host => Ok(host),
+    }
+}
+
+
 pub fn parse_port<'a>(input: &'a str, scheme_type: SchemeType, parser: &UrlParser)
-                      -> ParseResult<(Option<u16>, Option<u16>, &'a str)> {
+                      -> LocatedResult<(Option<u16>, Option<u16>, &'a str)> {
+    let invalid_port = || {
+        let end = input.find(&['/', '\\', '?', '#'][..]).unwrap_or(input.len());
+        parser.fatal_error_in(ParseError::InvalidPort, input, end, "port")
+    };
     let mut port = 0;
     let mut has_any_digit = false;
     let mut end = input.len();
@@ -429,7 +598,7 @@ pub fn parse_port<'a>(input: &'a str, scheme_type: SchemeType, parser: &UrlParse
             '0'...'9' => {
                 port = port * 10 + (c as u32 - '0' as u32);
                 if port > ::std::u16::MAX as u32 {
-                    return Err(ParseError::InvalidPort)
+                    return invalid_port()
                 }
                 has_any_digit = true;
             },
@@ -437,8 +606,9 @@ pub fn parse_port<'a>(input: &'a str, scheme_type: SchemeType, parser: &UrlParse
                 end = i;
                 break
             },
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
-            _ => return Err(ParseError::InvalidPort)
+            '\t' | '\n' | '\r' => try!(parser.parse_error(
+                ParseError::InvalidCharacter, &input[i..], "port")),
+            _ => return invalid_port()
         }
     }
     let default_port = scheme_type.default_port();
@@ -450,7 +620,8 @@ pub fn parse_port<'a>(input: &'a str, scheme_type: SchemeType, parser: &UrlParse
 }
 
 
-fn parse_file_host<'a>(input: &'a str, parser: &UrlParser) -> ParseResult<(Host, &'a str)> {
+fn parse_file_host<'a>(input: &'a str, scheme: &str, parser: &UrlParser)
+                       -> LocatedResult<(Host, &'a str)> {
     let mut host_input = String::new();
     let mut end = input.len();
     for (i, c) in input.char_indices() {
@@ -459,26 +630,28 @@ fn parse_file_host<'a>(input: &'a str, parser: &UrlParser) -> ParseResult<(Host,
                 end = i;
                 break
             },
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+            '\t' | '\n' | '\r' => try!(parser.parse_error(
+                ParseError::InvalidCharacter, &input[i..], "host")),
             _ => host_input.push(c)
         }
     }
     let host = if host_input.is_empty() {
         Host::Domain(String::new())
     } else {
-        try!(Host::parse(&host_input))
+        try!(parse_url_host(&host_input, scheme, parser)
+             .or_else(|error| parser.fatal_error(error, input, "host")))
     };
     Ok((host, &input[end..]))
 }
 
 
 pub fn parse_standalone_path(input: &str, parser: &UrlParser)
-                             -> ParseResult<(Vec<String>, Option<String>, Option<String>)> {
+                             -> LocatedResult<(Vec<String>, Option<String>, Option<String>)> {
     if !input.starts_with("/") {
         if input.starts_with("\\") {
-            try!(parser.parse_error(ParseError::InvalidBackslash));
+            try!(parser.parse_error(ParseError::InvalidBackslash, input, "path"));
         } else {
-            return Err(ParseError::ExpectedInitialSlash)
+            return parser.fatal_error(ParseError::ExpectedInitialSlash, input, "path")
         }
     }
     let (path, remaining) = try!(parse_path(
@@ -490,13 +663,13 @@ pub fn parse_standalone_path(input: &str, parser: &UrlParser)
 
 pub fn parse_path_start<'a>(input: &'a str, context: Context, scheme_type: SchemeType,
                             parser: &UrlParser)
-                            -> ParseResult<(Vec<String>, &'a str)> {
+                            -> LocatedResult<(Vec<String>, &'a str)> {
     let mut i = 0;
     // Relative path start state
     match input.chars().next() {
         Some('/') => i = 1,
         Some('\\') => {
-            try!(parser.parse_error(ParseError::InvalidBackslash));
+            try!(parser.parse_error(ParseError::InvalidBackslash, input, "path"));
             i = 1;
         },
         _ => ()
@@ -507,7 +680,7 @@ pub fn parse_path_start<'a>(input: &'a str, context: Context, scheme_type: Schem
 
 fn parse_path<'a>(base_path: &[String], input: &'a str, context: Context,
                   scheme_type: SchemeType, parser: &UrlParser)
-                  -> ParseResult<(Vec<String>, &'a str)> {
+                  -> LocatedResult<(Vec<String>, &'a str)> {
     // Relative path state
     let mut path = base_path.to_vec();
     let mut iter = input.char_ranges();
@@ -524,7 +697,7 @@ fn parse_path<'a>(base_path: &[String], input: &'a str, context: Context,
                     break
                 },
                 '\\' => {
-                    try!(parser.parse_error(ParseError::InvalidBackslash));
+                    try!(parser.parse_error(ParseError::InvalidBackslash, &input[i..], "path"));
                     ends_with_slash = true;
                     end = i;
                     break
@@ -533,23 +706,23 @@ fn parse_path<'a>(base_path: &[String], input: &'a str, context: Context,
                     end = i;
                     break
                 },
-                '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+                '\t' | '\n' | '\r' => try!(parser.parse_error(
+                    ParseError::InvalidCharacter, &input[i..], "path")),
                 _ => {
-                    try!(check_url_code_point(input, i, c, parser));
+                    try!(check_url_code_point(input, i, c, "path", parser));
                     utf8_percent_encode_to(&input[i..next_i],
                                         DEFAULT_ENCODE_SET, &mut path_part);
                 }
             }
         }
         match &*path_part {
-            ".." | ".%2e" | ".%2E" | "%2e." | "%2E." |
-            "%2e%2e" | "%2E%2e" | "%2e%2E" | "%2E%2E" => {
+            part if is_double_dot_path_segment(part) => {
                 path.pop();
                 if !ends_with_slash {
                     path.push(String::new());
                 }
             },
-            "." | "%2e" | "%2E" => {
+            part if is_single_dot_path_segment(part) => {
                 if !ends_with_slash {
                     path.push(String::new());
                 }
@@ -568,6 +741,11 @@ fn parse_path<'a>(base_path: &[String], input: &'a str, context: Context,
                 path.push(path_part)
             }
         }
+        if let Some(max_path_segments) = parser.max_path_segments {
+            if path.len() > max_path_segments {
+                return parser.fatal_error(ParseError::TooManyPathSegments, &input[end..], "path")
+            }
+        }
         if !ends_with_slash {
             break
         }
@@ -576,8 +754,23 @@ fn parse_path<'a>(base_path: &[String], input: &'a str, context: Context,
 }
 
 
+/// Return whether a percent-encoded path component is `..`, which removes the previous one.
+#[inline]
+pub fn is_double_dot_path_segment(segment: &str) -> bool {
+    matches!(segment, ".." | ".%2e" | ".%2E" | "%2e." | "%2E." |
+                      "%2e%2e" | "%2E%2e" | "%2e%2E" | "%2E%2E")
+}
+
+
+/// Return whether a percent-encoded path component is `.`, which is removed.
+#[inline]
+pub fn is_single_dot_path_segment(segment: &str) -> bool {
+    matches!(segment, "." | "%2e" | "%2E")
+}
+
+
 fn parse_scheme_data<'a>(input: &'a str, parser: &UrlParser)
-                         -> ParseResult<(String, &'a str)> {
+                         -> LocatedResult<(String, &'a str)> {
     let mut scheme_data = String::new();
     let mut end = input.len();
     for (i, c, next_i) in input.char_ranges() {
@@ -586,9 +779,10 @@ fn parse_scheme_data<'a>(input: &'a str, parser: &UrlParser)
                 end = i;
                 break
             },
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+            '\t' | '\n' | '\r' => try!(parser.parse_error(
+                ParseError::InvalidCharacter, &input[i..], "scheme data")),
             _ => {
-                try!(check_url_code_point(input, i, c, parser));
+                try!(check_url_code_point(input, i, c, "scheme data", parser));
                 utf8_percent_encode_to(&input[i..next_i],
                                     SIMPLE_ENCODE_SET, &mut scheme_data);
             }
@@ -599,14 +793,19 @@ fn parse_scheme_data<'a>(input: &'a str, parser: &UrlParser)
 
 
 fn parse_query_and_fragment(input: &str, parser: &UrlParser)
-                            -> ParseResult<(Option<String>, Option<String>)> {
+                            -> LocatedResult<(Option<String>, Option<String>)> {
     match input.chars().next() {
-        Some('#') => Ok((None, Some(try!(parse_fragment(&input[1..], parser))))),
+        Some('#') => {
+            let fragment = try!(parse_fragment(&input[1..], Context::UrlParser, parser));
+            Ok((None, Some(fragment)))
+        },
         Some('?') => {
             let (query, remaining) = try!(parse_query(
                 &input[1..], Context::UrlParser, parser));
             let fragment = match remaining {
-                Some(remaining) => Some(try!(parse_fragment(remaining, parser))),
+                Some(remaining) => {
+                    Some(try!(parse_fragment(remaining, Context::UrlParser, parser)))
+                },
                 None => None
             };
             Ok((Some(query), fragment))
@@ -619,7 +818,7 @@ fn parse_query_and_fragment(input: &str, parser: &UrlParser)
 
 
 pub fn parse_query<'a>(input: &'a str, context: Context, parser: &UrlParser)
-                   -> ParseResult<(String, Option<&'a str>)> {
+                   -> LocatedResult<(String, Option<&'a str>)> {
     let mut query = String::new();
     let mut remaining = None;
     for (i, c) in input.char_indices() {
@@ -628,28 +827,61 @@ pub fn parse_query<'a>(input: &'a str, context: Context, parser: &UrlParser)
                 remaining = Some(&input[i + 1..]);
                 break
             },
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+            '\t' | '\n' | '\r' => try!(parser.parse_error(
+                ParseError::InvalidCharacter, &input[i..], "query")),
             _ => {
-                try!(check_url_code_point(input, i, c, parser));
+                try!(check_url_code_point(input, i, c, "query", parser));
                 query.push(c);
             }
         }
     }
 
     let query_bytes = parser.query_encoding_override.encode(&query);
-    Ok((percent_encode(&query_bytes, QUERY_ENCODE_SET), remaining))
+    let query = percent_encode(&query_bytes, QUERY_ENCODE_SET);
+    if parser.forbid_duplicate_query_keys {
+        // Names are compared as percent-decoded bytes, since lossy UTF-8 decoding
+        // would make distinct names collide. Each `&`-separated piece of the encoded query
+        // comes from the piece of the input at the same index, where errors are reported.
+        let end = remaining.map_or(input.len(), |remaining| input.len() - remaining.len() - 1);
+        let mut names = HashSet::new();
+        let mut start = 0;
+        for (piece, input_piece) in query.split('&').zip(input[..end].split('&')) {
+            let at = &input[start..];
+            start += input_piece.len() + 1;
+            if piece.is_empty() {
+                continue
+            }
+            let name = match piece.find('=') {
+                Some(position) => &piece[..position],
+                None => piece,
+            };
+            let name: Vec<u8> = name.bytes().map(|b| if b == b'+' { b' ' } else { b }).collect();
+            if !names.insert(percent_decode(&name).collect::<Vec<u8>>()) {
+                let len = input_piece.find('=').unwrap_or(input_piece.len());
+                return parser.fatal_error_in(ParseError::DuplicateQueryKey, at, len, "query")
+            }
+        }
+    }
+    Ok((query, remaining))
 }
 
 
-pub fn parse_fragment<'a>(input: &'a str, parser: &UrlParser) -> ParseResult<String> {
+pub fn parse_fragment<'a>(input: &'a str, context: Context, parser: &UrlParser)
+                          -> LocatedResult<String> {
+    // The parser keeps some characters as-is for compatibility with existing content,
+    // but the setter encodes them so that the URL can be parsed back.
+    let encode_set = match context {
+        Context::UrlParser => SIMPLE_ENCODE_SET,
+        Context::Setter => FRAGMENT_ENCODE_SET,
+    };
     let mut fragment = String::new();
     for (i, c, next_i) in input.char_ranges() {
         match c {
-            '\t' | '\n' | '\r' => try!(parser.parse_error(ParseError::InvalidCharacter)),
+            '\t' | '\n' | '\r' => try!(parser.parse_error(
+                ParseError::InvalidCharacter, &input[i..], "fragment")),
             _ => {
-                try!(check_url_code_point(input, i, c, parser));
-                utf8_percent_encode_to(&input[i..next_i],
-                                    SIMPLE_ENCODE_SET, &mut fragment);
+                try!(check_url_code_point(input, i, c, "fragment", parser));
+                utf8_percent_encode_to(&input[i..next_i], encode_set, &mut fragment);
             }
         }
     }
@@ -736,14 +968,15 @@ impl<'a> Iterator for CharRanges<'a> {
 }
 
 #[inline]
-fn check_url_code_point(input: &str, i: usize, c: char, parser: &UrlParser)
-                        -> ParseResult<()> {
+fn check_url_code_point(input: &str, i: usize, c: char, component: &'static str,
+                        parser: &UrlParser)
+                        -> LocatedResult<()> {
     if c == '%' {
         if !starts_with_2_hex(&input[i + 1..]) {
-            try!(parser.parse_error(ParseError::InvalidPercentEncoded));
+            try!(parser.parse_error(ParseError::InvalidPercentEncoded, &input[i..], component));
         }
     } else if !is_url_code_point(c) {
-        try!(parser.parse_error(ParseError::NonUrlCodePoint));
+        try!(parser.parse_error(ParseError::NonUrlCodePoint, &input[i..], component));
     }
     Ok(())
 }
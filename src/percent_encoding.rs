@@ -6,6 +6,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::borrow::Cow;
+use std::slice;
+use std::str;
 
 #[path = "encode_sets.rs"]
 mod encode_sets;
@@ -21,43 +24,145 @@ mod encode_sets;
 /// In the query string however, a question mark does not have any special meaning
 /// and does not need to be percent-encoded.
 ///
-/// Since the implementation details of `EncodeSet` are private,
-/// the set of available encode sets is not extensible beyond the ones
-/// provided here.
-/// If you need a different encode set,
-/// please [file a bug](https://github.com/servo/rust-url/issues)
-/// explaining the use case.
-#[derive(Copy, Clone)]
+/// The encode sets provided here percent-encode C0 control bytes and bytes outside the ASCII range.
+/// The latter include the UTF-8 encoding of C1 control code points (U+0080 to U+009F).
+///
+/// If you need a different encode set, derive it from an existing one
+/// with the `add` and `remove` methods, or use an `EncodeSetBuilder`:
+///
+/// ```rust
+/// use url::percent_encoding::{SIMPLE_ENCODE_SET, utf8_percent_encode};
+///
+/// // Only encode control characters, space, and commas.
+/// let encode_set = SIMPLE_ENCODE_SET.add(b' ').add(b',');
+/// assert_eq!(utf8_percent_encode("a, b\n", encode_set), "a%2C%20b%0A");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct EncodeSet {
-    map: &'static [&'static str; 256],
+    /// Bit `b % 32` of `bits[b / 32]` is set if byte `b` is percent-encoded.
+    bits: [u32; 8],
+}
+
+impl EncodeSet {
+    /// Return whether the given byte is percent-encoded in this set.
+    #[inline]
+    pub fn contains(&self, byte: u8) -> bool {
+        self.bits[byte as usize / 32] & (1 << (byte % 32)) != 0
+    }
+
+    /// Return a copy of this set that also percent-encodes the given byte.
+    #[inline]
+    pub fn add(self, byte: u8) -> EncodeSet {
+        EncodeSetBuilder::new(self).add(byte).build()
+    }
+
+    /// Return a copy of this set that does not percent-encode the given byte.
+    ///
+    /// Like `EncodeSetBuilder::remove`, this has no effect for bytes outside the ASCII range.
+    #[inline]
+    pub fn remove(self, byte: u8) -> EncodeSet {
+        EncodeSetBuilder::new(self).remove(byte).build()
+    }
+}
+
+
+/// Builds a custom `EncodeSet`, starting from an existing one or from a set that encodes all bytes.
+///
+/// ```rust
+/// use url::percent_encoding::{EncodeSetBuilder, utf8_percent_encode};
+///
+/// // Encode everything except lower case ASCII letters and `-`.
+/// let mut builder = EncodeSetBuilder::encode_all();
+/// for byte in b'a'..b'z' + 1 {
+///     builder.remove(byte);
+/// }
+/// let encode_set = builder.remove(b'-').build();
+/// assert_eq!(utf8_percent_encode("foo-Bar_1", encode_set), "foo-%42ar%5F%31");
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct EncodeSetBuilder {
+    bits: [u32; 8],
+}
+
+impl EncodeSetBuilder {
+    /// Start from the given encode set.
+    #[inline]
+    pub fn new(encode_set: EncodeSet) -> EncodeSetBuilder {
+        EncodeSetBuilder { bits: encode_set.bits }
+    }
+
+    /// Start from a set that percent-encodes every byte.
+    #[inline]
+    pub fn encode_all() -> EncodeSetBuilder {
+        EncodeSetBuilder { bits: [!0; 8] }
+    }
+
+    /// Percent-encode the given byte, and return the `EncodeSetBuilder`.
+    #[inline]
+    pub fn add(&mut self, byte: u8) -> &mut EncodeSetBuilder {
+        self.bits[byte as usize / 32] |= 1 << (byte % 32);
+        self
+    }
+
+    /// Do not percent-encode the given byte, and return the `EncodeSetBuilder`.
+    ///
+    /// Bytes outside the ASCII range are always percent-encoded,
+    /// so that percent-encoded strings are within the ASCII range:
+    /// for them, this has no effect.
+    #[inline]
+    pub fn remove(&mut self, byte: u8) -> &mut EncodeSetBuilder {
+        if byte < 0x80 {
+            self.bits[byte as usize / 32] &= !(1 << (byte % 32));
+        }
+        self
+    }
+
+    /// Return the built encode set.
+    #[inline]
+    pub fn build(&self) -> EncodeSet {
+        EncodeSet { bits: self.bits }
+    }
 }
 
 /// This encode set is used for fragment identifier and non-relative scheme data.
-pub static SIMPLE_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::SIMPLE };
+///
+/// It only contains control characters (C0 controls and U+007F delete)
+/// and bytes outside the ASCII range,
+/// which makes it a good starting point for custom encode sets.
+pub static SIMPLE_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::SIMPLE };
+
+/// This encode set is used when setting the fragment identifier of a parsed URL.
+pub static FRAGMENT_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::FRAGMENT };
 
 /// This encode set is used in the URL parser for query strings.
-pub static QUERY_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::QUERY };
+pub static QUERY_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::QUERY };
 
 /// This encode set is used for path components.
-pub static DEFAULT_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::DEFAULT };
+pub static DEFAULT_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::DEFAULT };
 
 /// This encode set is used in the URL parser for usernames and passwords.
-pub static USERINFO_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::USERINFO };
+pub static USERINFO_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::USERINFO };
 
 /// This encode set should be used when setting the password field of a parsed URL.
-pub static PASSWORD_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::PASSWORD };
+pub static PASSWORD_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::PASSWORD };
 
 /// This encode set should be used when setting the username field of a parsed URL.
-pub static USERNAME_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::USERNAME };
+pub static USERNAME_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::USERNAME };
 
 /// This encode set is used in `application/x-www-form-urlencoded` serialization.
 pub static FORM_URLENCODED_ENCODE_SET: EncodeSet = EncodeSet {
-    map: &encode_sets::FORM_URLENCODED,
+    bits: encode_sets::FORM_URLENCODED,
 };
 
 /// This encode set is used for HTTP header values and is defined at
 /// https://tools.ietf.org/html/rfc5987#section-3.2
-pub static HTTP_VALUE_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::HTTP_VALUE };
+pub static HTTP_VALUE_ENCODE_SET: EncodeSet = EncodeSet { bits: encode_sets::HTTP_VALUE };
+
+/// This encode set is used for values substituted into an `UrlTemplate`.
+/// It encodes everything except the *unreserved* characters of RFC 3986.
+pub static TEMPLATE_VALUE_ENCODE_SET: EncodeSet = EncodeSet {
+    bits: encode_sets::TEMPLATE_VALUE,
+};
 
 /// Percent-encode the given bytes, and push the result to `output`.
 ///
@@ -65,7 +170,11 @@ pub static HTTP_VALUE_ENCODE_SET: EncodeSet = EncodeSet { map: &encode_sets::HTT
 #[inline]
 pub fn percent_encode_to(input: &[u8], encode_set: EncodeSet, output: &mut String) {
     for &byte in input {
-        output.push_str(encode_set.map[byte as usize])
+        if encode_set.contains(byte) {
+            output.push_str(encode_sets::PERCENT_ENCODED[byte as usize])
+        } else {
+            output.push(byte as char)
+        }
     }
 }
 
@@ -101,31 +210,162 @@ pub fn utf8_percent_encode(input: &str, encode_set: EncodeSet) -> String {
 }
 
 
+/// Percent-encode the UTF-8 encoding of the given string.
+///
+/// Like `utf8_percent_encode`, but the input is borrowed as-is
+/// if it contains no byte in the encode set.
+///
+/// ```rust
+/// use std::borrow::Cow;
+/// use url::percent_encoding::{utf8_percent_encode_cow, DEFAULT_ENCODE_SET};
+///
+/// match utf8_percent_encode_cow("abc", DEFAULT_ENCODE_SET) {
+///     Cow::Borrowed(s) => assert_eq!(s, "abc"),
+///     Cow::Owned(_) => panic!("nothing needed encoding"),
+/// }
+/// assert_eq!(utf8_percent_encode_cow("a b", DEFAULT_ENCODE_SET), "a%20b");
+/// ```
+#[inline]
+pub fn utf8_percent_encode_cow(input: &str, encode_set: EncodeSet) -> Cow<str> {
+    match input.bytes().position(|byte| encode_set.contains(byte)) {
+        // Non-ASCII bytes are always encoded, so `first` is at a char boundary.
+        Some(first) => {
+            let mut output = String::with_capacity(input.len() + 2);
+            output.push_str(&input[..first]);
+            utf8_percent_encode_to(&input[first..], encode_set, &mut output);
+            Cow::Owned(output)
+        }
+        None => Cow::Borrowed(input),
+    }
+}
+
+
 /// Percent-decode the given bytes, and push the result to `output`.
 pub fn percent_decode_to(input: &[u8], output: &mut Vec<u8>) {
-    let mut i = 0;
-    while i < input.len() {
-        let c = input[i];
-        if c == b'%' && i + 2 < input.len() {
-            if let (Some(h), Some(l)) = (from_hex(input[i + 1]), from_hex(input[i + 2])) {
-                output.push(h * 0x10 + l);
-                i += 3;
-                continue
+    output.extend(percent_decode(input))
+}
+
+
+/// Percent-decode the given bytes.
+///
+/// The return value is an iterator of decoded `u8` bytes,
+/// that can also be converted to a `Cow<[u8]>` or decoded as UTF-8
+/// without allocating if nothing was percent-encoded.
+#[inline]
+pub fn percent_decode(input: &[u8]) -> PercentDecode {
+    PercentDecode {
+        bytes: input.iter()
+    }
+}
+
+/// Percent-decode the given string, and decode the result as UTF-8.
+///
+/// This is “lossy”: invalid UTF-8 percent-encoded byte sequences
+/// will be replaced � U+FFFD, the replacement character.
+///
+/// The input is borrowed as-is if it contains no percent-encoded bytes.
+#[inline]
+pub fn percent_decode_str(input: &str) -> Cow<str> {
+    percent_decode(input.as_bytes()).decode_utf8_lossy()
+}
+
+/// The return type of `percent_decode`.
+#[derive(Clone)]
+pub struct PercentDecode<'a> {
+    bytes: slice::Iter<'a, u8>,
+}
+
+fn after_percent_sign(iter: &mut slice::Iter<u8>) -> Option<u8> {
+    let initial_iter = iter.clone();
+    let h = iter.next().and_then(|&b| from_hex(b));
+    let l = iter.next().and_then(|&b| from_hex(b));
+    if let (Some(h), Some(l)) = (h, l) {
+        Some(h * 0x10 + l)
+    } else {
+        *iter = initial_iter;
+        None
+    }
+}
+
+impl<'a> Iterator for PercentDecode<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.bytes.next().map(|&byte| {
+            if byte == b'%' {
+                after_percent_sign(&mut self.bytes).unwrap_or(byte)
+            } else {
+                byte
             }
-        }
+        })
+    }
 
-        output.push(c);
-        i += 1;
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let bytes = self.bytes.len();
+        (bytes / 3, Some(bytes))
     }
 }
 
+impl<'a> From<PercentDecode<'a>> for Cow<'a, [u8]> {
+    fn from(iter: PercentDecode<'a>) -> Self {
+        match iter.if_any() {
+            Some(vec) => Cow::Owned(vec),
+            None => Cow::Borrowed(iter.bytes.as_slice()),
+        }
+    }
+}
 
-/// Percent-decode the given bytes.
-#[inline]
-pub fn percent_decode(input: &[u8]) -> Vec<u8> {
-    let mut output = Vec::new();
-    percent_decode_to(input, &mut output);
-    output
+impl<'a> PercentDecode<'a> {
+    /// If the percent-decoding is different from the input, return it as a new bytes vector.
+    pub fn if_any(&self) -> Option<Vec<u8>> {
+        let mut bytes_iter = self.bytes.clone();
+        while bytes_iter.any(|&b| b == b'%') {
+            if let Some(decoded_byte) = after_percent_sign(&mut bytes_iter) {
+                let initial_bytes = self.bytes.as_slice();
+                let unchanged_bytes_len = initial_bytes.len() - bytes_iter.len() - 3;
+                let mut decoded = initial_bytes[..unchanged_bytes_len].to_owned();
+                decoded.push(decoded_byte);
+                decoded.extend(PercentDecode {
+                    bytes: bytes_iter
+                });
+                return Some(decoded)
+            }
+        }
+        // Nothing to decode
+        None
+    }
+
+    /// Decode the result of percent-decoding as UTF-8.
+    ///
+    /// This is “lossy”: invalid UTF-8 percent-encoded byte sequences
+    /// will be replaced � U+FFFD, the replacement character.
+    ///
+    /// The input is borrowed as-is if it contains no percent-encoded bytes
+    /// and is valid UTF-8.
+    pub fn decode_utf8_lossy(self) -> Cow<'a, str> {
+        match self.clone().into() {
+            Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+            Cow::Owned(bytes) => Cow::Owned(match String::from_utf8(bytes) {
+                Ok(string) => string,
+                Err(error) => String::from_utf8_lossy(error.as_bytes()).into_owned(),
+            }),
+        }
+    }
+
+    /// Decode the result of percent-decoding as UTF-8.
+    ///
+    /// Unlike `decode_utf8_lossy`, this is strict:
+    /// invalid UTF-8 percent-encoded byte sequences return an error.
+    ///
+    /// The input is borrowed as-is if it contains no percent-encoded bytes.
+    pub fn decode_utf8(self) -> Result<Cow<'a, str>, str::Utf8Error> {
+        match self.clone().into() {
+            Cow::Borrowed(bytes) => str::from_utf8(bytes).map(Cow::Borrowed),
+            Cow::Owned(bytes) => {
+                String::from_utf8(bytes).map(Cow::Owned).map_err(|error| error.utf8_error())
+            }
+        }
+    }
 }
 
 
@@ -135,7 +375,7 @@ pub fn percent_decode(input: &[u8]) -> Vec<u8> {
 /// will be replaced � U+FFFD, the replacement character.
 #[inline]
 pub fn lossy_utf8_percent_decode(input: &[u8]) -> String {
-    String::from_utf8_lossy(&percent_decode(input)).to_string()
+    percent_decode(input).decode_utf8_lossy().into_owned()
 }
 
 /// Convert the given hex character into its numeric value.
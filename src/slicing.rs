@@ -0,0 +1,135 @@
+// Copyright 2016 Simon Sapin.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Byte ranges of URL components in the serialization of an URL.
+
+use std::ops::{Range, RangeFrom, RangeTo, RangeFull};
+use super::{Url, SchemeData};
+use format::PathFormatter;
+
+/// A position in the serialization of an URL, just before or just after one of its components.
+///
+/// The delimiters (such as `://`, `@`, or `?`) are not part of the components.
+/// A missing component is empty, and placed where it would be if present:
+/// for example, `BeforeQuery` and `AfterQuery` are both at the end of the path
+/// if there is no query string.
+///
+/// The username, password, host, and port of a *non-relative* URL
+/// are empty and placed at the start of the scheme data, which is taken as the path.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+pub enum Position {
+    BeforeScheme,
+    AfterScheme,
+    BeforeUsername,
+    AfterUsername,
+    BeforePassword,
+    AfterPassword,
+    BeforeHost,
+    AfterHost,
+    BeforePort,
+    AfterPort,
+    BeforePath,
+    AfterPath,
+    BeforeQuery,
+    AfterQuery,
+    BeforeFragment,
+    AfterFragment,
+}
+
+
+/// A range of positions, as taken by `Url::slice`: `from..to`, `from..`, `..to`, or `..`.
+pub trait PositionRange {
+    /// Return the start and end positions, with `BeforeScheme` and `AfterFragment`
+    /// for an open start or end.
+    fn bounds(&self) -> (Position, Position);
+}
+
+impl PositionRange for Range<Position> {
+    fn bounds(&self) -> (Position, Position) { (self.start, self.end) }
+}
+
+impl PositionRange for RangeFrom<Position> {
+    fn bounds(&self) -> (Position, Position) { (self.start, Position::AfterFragment) }
+}
+
+impl PositionRange for RangeTo<Position> {
+    fn bounds(&self) -> (Position, Position) { (Position::BeforeScheme, self.end) }
+}
+
+impl PositionRange for RangeFull {
+    fn bounds(&self) -> (Position, Position) { (Position::BeforeScheme, Position::AfterFragment) }
+}
+
+
+/// Return the byte range between two positions in `url.serialize()`.
+///
+/// Panics if `from` is after `to`.
+pub fn byte_range(url: &Url, from: Position, to: Position) -> Range<usize> {
+    assert!(from <= to, "{:?} is after {:?}", from, to);
+    let positions = positions(url);
+    positions[from as usize]..positions[to as usize]
+}
+
+
+fn positions(url: &Url) -> [usize; 16] {
+    let mut positions = [0; 16];
+    let after_scheme = url.scheme.len();
+    positions[Position::AfterScheme as usize] = after_scheme;
+
+    let after_path = match url.scheme_data {
+        SchemeData::Relative(ref scheme_data) => {
+            let before_username = after_scheme + "://".len();
+            let after_username = before_username + scheme_data.username.len();
+            let (before_password, after_password) = match scheme_data.password {
+                Some(ref password) => (after_username + 1, after_username + 1 + password.len()),
+                None => (after_username, after_username),
+            };
+            let has_userinfo = !scheme_data.username.is_empty() || scheme_data.password.is_some();
+            let before_host = if has_userinfo { after_password + 1 } else { after_password };
+            let after_host = before_host + scheme_data.host.to_string().len();
+            let (before_port, after_port) = match scheme_data.port {
+                Some(port) => (after_host + 1, after_host + 1 + port.to_string().len()),
+                None => (after_host, after_host),
+            };
+            positions[Position::BeforeUsername as usize] = before_username;
+            positions[Position::AfterUsername as usize] = after_username;
+            positions[Position::BeforePassword as usize] = before_password;
+            positions[Position::AfterPassword as usize] = after_password;
+            positions[Position::BeforeHost as usize] = before_host;
+            positions[Position::AfterHost as usize] = after_host;
+            positions[Position::BeforePort as usize] = before_port;
+            positions[Position::AfterPort as usize] = after_port;
+            positions[Position::BeforePath as usize] = after_port;
+            after_port + PathFormatter { path: &scheme_data.path }.to_string().len()
+        }
+        SchemeData::NonRelative(ref scheme_data) => {
+            let before_path = after_scheme + ":".len();
+            for position in &mut positions[Position::BeforeUsername as usize..
+                                           Position::AfterPath as usize] {
+                *position = before_path
+            }
+            before_path + scheme_data.len()
+        }
+    };
+    positions[Position::AfterPath as usize] = after_path;
+
+    let (before_query, after_query) = match url.query {
+        Some(ref query) => (after_path + 1, after_path + 1 + query.len()),
+        None => (after_path, after_path),
+    };
+    positions[Position::BeforeQuery as usize] = before_query;
+    positions[Position::AfterQuery as usize] = after_query;
+
+    let (before_fragment, after_fragment) = match url.fragment {
+        Some(ref fragment) => (after_query + 1, after_query + 1 + fragment.len()),
+        None => (after_query, after_query),
+    };
+    positions[Position::BeforeFragment as usize] = before_fragment;
+    positions[Position::AfterFragment as usize] = after_fragment;
+    positions
+}
@@ -0,0 +1,130 @@
+// Copyright 2016 Simon Sapin.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Expansion of [URI templates](https://tools.ietf.org/html/rfc6570) into URLs.
+//!
+//! Only a subset is supported:
+//! simple string expansion `{var}` (Level 1)
+//! and form-style query expansion `{?var}` or `{?var1,var2}`.
+
+use std::collections::HashMap;
+use parser::{ParseError, ParseResult};
+use percent_encoding::{utf8_percent_encode_to, TEMPLATE_VALUE_ENCODE_SET};
+use Url;
+
+
+/// A parsed URI template, that can be expanded into an URL.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use url::UrlTemplate;
+///
+/// let template = UrlTemplate::parse("http://example.net/users/{id}/posts{?page}").unwrap();
+/// let mut vars = HashMap::new();
+/// vars.insert("id", "Jane Doe");
+/// vars.insert("page", "2");
+/// let url = template.expand(&vars).unwrap();
+/// assert_eq!(url.serialize(), "http://example.net/users/Jane%20Doe/posts?page=2");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UrlTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// `{var}`
+    Simple(String),
+    /// `{?var1,var2}`
+    Query(Vec<String>),
+}
+
+
+impl UrlTemplate {
+    /// Parse a URI template.
+    ///
+    /// Returns `Err(ParseError::InvalidUrlTemplate)` for unbalanced braces,
+    /// invalid variable names, or expression operators other than `?`.
+    pub fn parse(template: &str) -> ParseResult<UrlTemplate> {
+        let mut parts = Vec::new();
+        let mut remaining = template;
+        while let Some(start) = remaining.find(|c| c == '{' || c == '}') {
+            if remaining[start..].starts_with('}') {
+                return Err(ParseError::InvalidUrlTemplate)
+            }
+            if start > 0 {
+                parts.push(Part::Literal(remaining[..start].to_owned()));
+            }
+            remaining = &remaining[start + 1..];
+            let end = match remaining.find('}') {
+                Some(end) => end,
+                None => return Err(ParseError::InvalidUrlTemplate),
+            };
+            let expression = &remaining[..end];
+            remaining = &remaining[end + 1..];
+            if expression.starts_with('?') {
+                let names = try!(expression[1..].split(',').map(parse_name).collect());
+                parts.push(Part::Query(names))
+            } else {
+                parts.push(Part::Simple(try!(parse_name(expression))))
+            }
+        }
+        if !remaining.is_empty() {
+            parts.push(Part::Literal(remaining.to_owned()));
+        }
+        Ok(UrlTemplate { parts: parts })
+    }
+
+    /// Substitute variables into the template, and parse the result as an URL.
+    ///
+    /// Values are percent-encoded so that they can not change the structure of the URL:
+    /// everything other than ASCII letters, digits, and `-._~` is encoded.
+    /// Per RFC 6570, an undefined variable expands to nothing in `{var}`
+    /// and is omitted from `{?var}`.
+    pub fn expand(&self, vars: &HashMap<&str, &str>) -> ParseResult<Url> {
+        let mut output = String::new();
+        for part in &self.parts {
+            match *part {
+                Part::Literal(ref literal) => output.push_str(literal),
+                Part::Simple(ref name) => {
+                    if let Some(value) = vars.get(&**name) {
+                        utf8_percent_encode_to(value, TEMPLATE_VALUE_ENCODE_SET, &mut output)
+                    }
+                }
+                Part::Query(ref names) => {
+                    let mut separator = "?";
+                    for name in names {
+                        if let Some(value) = vars.get(&**name) {
+                            output.push_str(separator);
+                            output.push_str(name);
+                            output.push_str("=");
+                            utf8_percent_encode_to(value, TEMPLATE_VALUE_ENCODE_SET, &mut output);
+                            separator = "&";
+                        }
+                    }
+                }
+            }
+        }
+        Url::parse(&output)
+    }
+}
+
+
+/// http://tools.ietf.org/html/rfc6570#section-2.3
+fn parse_name(name: &str) -> ParseResult<String> {
+    let valid = !name.is_empty() && name.chars().all(|c| match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '.' | '%' => true,
+        _ => false,
+    });
+    if valid {
+        Ok(name.to_owned())
+    } else {
+        Err(ParseError::InvalidUrlTemplate)
+    }
+}
@@ -10,9 +10,8 @@
 //! These methods are not meant for use in Rust code,
 //! only to help implement the JavaScript URLUtils API: http://url.spec.whatwg.org/#urlutils
 
-use super::{Url, UrlParser, SchemeType, SchemeData, RelativeSchemeData};
+use super::{Url, UrlParser, SchemeType, SchemeData, RelativeSchemeData, Host};
 use parser::{ParseError, ParseResult, Context};
-use percent_encoding::{utf8_percent_encode_to, USERNAME_ENCODE_SET, PASSWORD_ENCODE_SET};
 
 
 #[allow(dead_code)]
@@ -23,6 +22,7 @@ pub struct UrlUtilsWrapper<'a> {
 
 #[doc(hidden)]
 pub trait UrlUtils {
+    fn set_href(&mut self, input: &str) -> ParseResult<()>;
     fn set_scheme(&mut self, input: &str) -> ParseResult<()>;
     fn set_username(&mut self, input: &str) -> ParseResult<()>;
     fn set_password(&mut self, input: &str) -> ParseResult<()>;
@@ -35,26 +35,67 @@ pub trait UrlUtils {
 }
 
 impl<'a> UrlUtils for UrlUtilsWrapper<'a> {
+    /// `URLUtils.href` setter
+    ///
+    /// The input is parsed as an absolute URL with the parameters of `self.parser`,
+    /// and replaces the whole URL. On error, the URL is unchanged.
+    fn set_href(&mut self, input: &str) -> ParseResult<()> {
+        *self.url = try!(self.parser.parse(input));
+        Ok(())
+    }
+
     /// `URLUtils.protocol` setter
+    ///
+    /// See `Url::set_scheme` for the allowed transitions.
     fn set_scheme(&mut self, input: &str) -> ParseResult<()> {
-        match ::parser::parse_scheme(input, Context::Setter) {
-            Some((scheme, _)) => {
-                if self.parser.get_scheme_type(&self.url.scheme).same_as(self.parser.get_scheme_type(&scheme)) {
-                    return Err(ParseError::InvalidScheme);
+        let scheme = match ::parser::parse_scheme(input, Context::Setter) {
+            Some((scheme, _)) => scheme,
+            None => return Err(ParseError::InvalidScheme),
+        };
+        let old_type = self.parser.get_scheme_type(&self.url.scheme);
+        let new_type = self.parser.get_scheme_type(&scheme);
+        // Relative and file-like schemes have scheme data of the same shape,
+        // non-relative schemes do not.
+        if old_type.same_as(SchemeType::NonRelative) != new_type.same_as(SchemeType::NonRelative) {
+            return Err(ParseError::InvalidScheme)
+        }
+        if let SchemeData::Relative(ref mut data) = self.url.scheme_data {
+            match new_type {
+                SchemeType::FileLike => {
+                    if !data.username.is_empty() || data.password.is_some()
+                            || data.port.is_some() {
+                        return Err(ParseError::InvalidScheme)
+                    }
+                    data.default_port = None;
                 }
-                self.url.scheme = scheme;
-                Ok(())
-            },
-            None => Err(ParseError::InvalidScheme),
+                SchemeType::Relative(default_port) => {
+                    if data.host == Host::Domain(String::new()) {
+                        return Err(ParseError::EmptyHost)
+                    }
+                    if data.port == Some(default_port) {
+                        data.port = None;
+                    }
+                    data.default_port = Some(default_port);
+                }
+                SchemeType::NonRelative => {}
+            }
         }
+        self.url.scheme = scheme;
+        Ok(())
     }
 
     /// `URLUtils.username` setter
+    ///
+    /// The input is checked like userinfo in the URL parser, reporting to its error handler,
+    /// and percent-encoded so that the serialization parses back to the same username.
     fn set_username(&mut self, input: &str) -> ParseResult<()> {
         match self.url.scheme_data {
             SchemeData::Relative(RelativeSchemeData { ref mut username, .. }) => {
-                username.truncate(0);
-                utf8_percent_encode_to(input, USERNAME_ENCODE_SET, username);
+                let scheme_type = self.parser.get_scheme_type(&self.url.scheme);
+                if scheme_type == SchemeType::FileLike {
+                    return Err(ParseError::CannotSetUsernameWithFileLikeScheme);
+                }
+                *username = try!(::parser::parse_username(input, Context::Setter, self.parser));
                 Ok(())
             },
             SchemeData::NonRelative(_) => Err(ParseError::CannotSetUsernameWithNonRelativeScheme)
@@ -62,16 +103,22 @@ impl<'a> UrlUtils for UrlUtilsWrapper<'a> {
     }
 
     /// `URLUtils.password` setter
+    ///
+    /// The input is checked like userinfo in the URL parser, reporting to its error handler,
+    /// and percent-encoded so that the serialization parses back to the same password.
     fn set_password(&mut self, input: &str) -> ParseResult<()> {
         match self.url.scheme_data {
             SchemeData::Relative(RelativeSchemeData { ref mut password, .. }) => {
+                let scheme_type = self.parser.get_scheme_type(&self.url.scheme);
+                if scheme_type == SchemeType::FileLike {
+                    return Err(ParseError::CannotSetPasswordWithFileLikeScheme);
+                }
                 if input.len() == 0 {
                     *password = None;
                     return Ok(());
                 }
-                let mut new_password = String::new();
-                utf8_percent_encode_to(input, PASSWORD_ENCODE_SET, &mut new_password);
-                *password = Some(new_password);
+                *password = Some(try!(::parser::parse_password(
+                    input, Context::Setter, self.parser)));
                 Ok(())
             },
             SchemeData::NonRelative(_) => Err(ParseError::CannotSetPasswordWithNonRelativeScheme)
@@ -86,7 +133,7 @@ impl<'a> UrlUtils for UrlUtilsWrapper<'a> {
             }) => {
                 let scheme_type = self.parser.get_scheme_type(&self.url.scheme);
                 let (new_host, new_port, new_default_port, _) = try!(::parser::parse_host(
-                    input, scheme_type, self.parser));
+                    input, &self.url.scheme, scheme_type, self.parser));
                 *host = new_host;
                 *port = new_port;
                 *default_port = new_default_port;
@@ -97,11 +144,29 @@ impl<'a> UrlUtils for UrlUtilsWrapper<'a> {
     }
 
     /// `URLUtils.hostname` setter
+    ///
+    /// An empty host removes the host in file-like schemes, and is an error in other schemes.
     fn set_host(&mut self, input: &str) -> ParseResult<()> {
         match self.url.scheme_data {
             SchemeData::Relative(RelativeSchemeData { ref mut host, .. }) => {
-                let (new_host, _) = try!(::parser::parse_hostname(input, self.parser));
-                *host = new_host;
+                let empty = input.is_empty() ||
+                    input.starts_with(|c| matches!(c, ':' | '/' | '\\' | '?' | '#'));
+                if empty && self.parser.get_scheme_type(&self.url.scheme) == SchemeType::FileLike {
+                    *host = Host::Domain(String::new());
+                    return Ok(())
+                }
+                // An IPv6 address without [] square brackets would otherwise be cut
+                // at its first `:` colon, taken as the start of a port number.
+                let bare_ipv6 = if input.starts_with('[') || !input.contains(':') { None } else {
+                    match Host::parse(input) {
+                        Ok(new_host @ Host::Ipv6(_)) => Some(new_host),
+                        _ => None,
+                    }
+                };
+                *host = match bare_ipv6 {
+                    Some(new_host) => new_host,
+                    None => try!(::parser::parse_hostname(input, &self.url.scheme, self.parser)).0,
+                };
                 Ok(())
             },
             SchemeData::NonRelative(_) => Err(ParseError::CannotSetHostWithNonRelativeScheme)
@@ -162,7 +227,7 @@ impl<'a> UrlUtils for UrlUtilsWrapper<'a> {
             None
         } else {
             let input = if input.starts_with("#") { &input[1..] } else { input };
-            Some(try!(::parser::parse_fragment(input, self.parser)))
+            Some(try!(::parser::parse_fragment(input, Context::Setter, self.parser)))
         };
         Ok(())
     }
@@ -27,3 +27,62 @@ fn test_form_serialize() {
     assert_eq!(serialize(pairs.iter().map(|p| (p.0, p.1))), want);
 
 }
+
+#[test]
+fn test_form_parse_preserving_empty_pairs() {
+    let pair = |name: &str, value: &str| Some((name.to_string(), value.to_string()));
+    assert_eq!(parse_preserving_empty_pairs(b"&&a=1&"), vec![None, None, pair("a", "1"), None]);
+    assert_eq!(parse_preserving_empty_pairs(b"a=1&=&b"),
+               vec![pair("a", "1"), pair("", ""), pair("b", "")]);
+    assert_eq!(parse_preserving_empty_pairs(b"&"), vec![None, None]);
+    assert_eq!(parse_preserving_empty_pairs(b""), vec![]);
+    assert_eq!(parse(b"&&a=1&"), vec![("a".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn test_form_parser() {
+    use std::borrow::Cow;
+
+    let input = b"a=1&&b+c=d%20e+f&g&=h&i=%FF&a=%zz";
+    let pairs: Vec<_> = Parser::new(input).collect();
+    assert_eq!(pairs, vec![
+        (Cow::Borrowed("a"), Cow::Borrowed("1")),
+        (Cow::Owned("b c".to_string()), Cow::Owned("d e f".to_string())),
+        (Cow::Borrowed("g"), Cow::Borrowed("")),
+        (Cow::Borrowed(""), Cow::Borrowed("h")),
+        (Cow::Borrowed("i"), Cow::Owned("\u{FFFD}".to_string())),
+        (Cow::Borrowed("a"), Cow::Borrowed("%zz")),
+    ]);
+    match pairs[0] {
+        (Cow::Borrowed(_), Cow::Borrowed(_)) => {}
+        _ => panic!("expected borrowed name and value"),
+    }
+    assert_eq!(parse(input), pairs.into_iter()
+        .map(|(name, value)| (name.into_owned(), value.into_owned())).collect::<Vec<_>>());
+    assert_eq!(Parser::new(b"").next(), None);
+    assert_eq!(Parser::new(b"&&").next(), None);
+}
+
+#[test]
+fn test_form_custom_separator() {
+    let pairs = [("a", "1"), ("b", "2")];
+    let encoded = serialize_with_separator(pairs.iter(), ';');
+    assert_eq!(encoded, "a=1;b=2");
+    assert_eq!(parse_with_separator(encoded.as_bytes(), ';'),
+               vec![("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())]);
+
+    let pairs = [("a&b", "c;d"), ("e", "f|g")];
+    let encoded = serialize_with_separator(pairs.iter(), '|');
+    assert_eq!(encoded, "a%26b=c%3Bd|e=f%7Cg");
+    assert_eq!(parse_with_separator(encoded.as_bytes(), '|'),
+               vec![("a&b".to_string(), "c;d".to_string()), ("e".to_string(), "f|g".to_string())]);
+    assert_eq!(parse_with_separator(b"a=1&b=2;;c", ';'),
+               vec![("a".to_string(), "1&b=2".to_string()), ("c".to_string(), "".to_string())]);
+    assert_eq!(Parser::with_separator(b"a=1;b=2", ';').count(), 2);
+}
+
+#[test]
+#[should_panic]
+fn test_form_non_ascii_separator() {
+    serialize_with_separator([("a", "1")].iter(), '\u{e9}');
+}
@@ -29,6 +29,20 @@ fn host() {
     assert_eq!(Host::parse("[0::ffff:0:2]").unwrap().to_string(), "[::ffff:0:2]");
 }
 
+#[test]
+fn host_variants() {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    assert_eq!(Host::Domain("example.com".to_owned()).to_string(), "example.com");
+    assert_eq!(Host::Ipv4(Ipv4Addr::new(1, 2, 3, 4)).to_string(), "1.2.3.4");
+    assert_eq!(Host::Ipv6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)).to_string(), "[::1]");
+    assert_eq!(Host::IpvFuture("v1.a".to_owned()).to_string(), "[v1.a]");
+    for input in &["example.com", "1.2.3.4", "[::1]", "[v1.a]"] {
+        assert_eq!(Host::parse(input).unwrap().to_string(), *input);
+        assert_eq!(Host::parse(input).unwrap().serialize(), *input);
+    }
+}
+
 #[test]
 fn userinfo_formatting() {
     // Test data as (username, password, result) tuples.
@@ -65,3 +79,16 @@ fn relative_scheme_url_formatting() {
         assert_eq!(url.to_string(), result.to_string());
     }
 }
+
+#[test]
+fn human_url_formatting() {
+    let url = Url::parse("http://user@☃.net:8080/a%20b/%C3%A9?q=%C3%A9t%C3%A9#f%20g").unwrap();
+    assert_eq!(format!("{}", url), "http://user@xn--n3h.net:8080/a%20b/%C3%A9?q=%C3%A9t%C3%A9#f%20g");
+    assert_eq!(format!("{:#}", url), "http://user@☃.net:8080/a b/é?q=été#f g");
+
+    let url = Url::parse("http://[::1]/").unwrap();
+    assert_eq!(format!("{:#}", url), "http://[::1]/");
+
+    let url = Url::parse("mailto:%C3%A9@example.com").unwrap();
+    assert_eq!(format!("{:#}", url), "mailto:é@example.com");
+}
@@ -73,6 +73,24 @@ fn test_uts46() {
     }
 }
 
+#[test]
+fn bidi_rule_applies_per_domain() {
+    fn to_ascii(domain: &str) -> Result<String, Vec<idna::Error>> {
+        idna::uts46_to_ascii(domain, idna::Uts46Flags {
+            use_std3_ascii_rules: false,
+            transitional_processing: false,
+            verify_dns_length: false,
+        })
+    }
+    // Without right-to-left characters, labels are not subject to the bidi rule.
+    assert_eq!(to_ascii("\u{2603}.net"), Ok("xn--n3h.net".to_owned()));
+    assert_eq!(to_ascii("xn--n3h.net"), Ok("xn--n3h.net".to_owned()));
+    assert_eq!(to_ascii("a.\u{5D0}"), Ok("a.xn--4db".to_owned()));
+    // With one, every label is, including left-to-right ones.
+    assert!(to_ascii("\u{2603}.\u{5D0}").is_err());
+    assert!(to_ascii("xn--n3h.xn--4db").is_err());
+}
+
 fn unescape(input: &str) -> String {
     let mut output = String::new();
     let mut chars = input.chars();
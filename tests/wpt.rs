@@ -42,6 +42,15 @@ fn run_one(entry: Entry) {
             }
             return
         }
+        if let Ok(ref url) = url {
+            if let Err(message) = check_invariants(url) {
+                if expected_failure {
+                    return
+                } else {
+                    panic!("{}", message)
+                }
+            }
+        }
         let Url { scheme, scheme_data, query, fragment, .. } = match url {
             Ok(url) => url,
             Err(message) => {
@@ -99,6 +108,17 @@ fn run_one(entry: Entry) {
     }
 }
 
+// Only checked with `cargo test --features check_invariants`.
+#[cfg(feature="check_invariants")]
+fn check_invariants(url: &Url) -> Result<(), String> {
+    url.check_invariants()
+}
+
+#[cfg(not(feature="check_invariants"))]
+fn check_invariants(_url: &Url) -> Result<(), String> {
+    Ok(())
+}
+
 // FIMXE: Remove this when &[&str]::join (the new name) lands in the stable channel.
 #[allow(deprecated)]
 fn str_join<T: ::std::borrow::Borrow<str>>(pieces: &[T], separator: &str) -> String {